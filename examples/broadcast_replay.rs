@@ -0,0 +1,47 @@
+// Stand-in for an ACC server's broadcasting interface, for testing live timing
+// without a running server. Waits for a client to register, then replays a
+// recording made with `LIVE_TIMING_RECORD` to it. `tests/data/broadcast_capture.bin`
+// is a short one.
+//
+// Usage: broadcast_replay <recording> [bind address] [milliseconds between packets]
+
+use std::{env, fs, net::UdpSocket, thread, time::Duration};
+
+fn main() -> std::io::Result<()> {
+    let mut args = env::args().skip(1);
+    let recording_path = args
+        .next()
+        .expect("Usage: broadcast_replay <recording> [bind address] [interval ms]");
+    let bind_address = args.next().unwrap_or_else(|| "127.0.0.1:9000".to_string());
+    let interval = Duration::from_millis(args.next().map_or(10, |ms| ms.parse().unwrap()));
+
+    // Length prefixed packets, the length being a little endian u32
+    let recording = fs::read(recording_path)?;
+    let mut packets = Vec::new();
+    let mut rest = recording.as_slice();
+    while rest.len() >= 4 {
+        let (length, remainder) = rest.split_at(4);
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        let (packet, remainder) = remainder.split_at(length);
+        packets.push(packet);
+        rest = remainder;
+    }
+
+    let socket = UdpSocket::bind(&bind_address)?;
+    println!("Waiting for a client on {bind_address}");
+    let mut buffer = [0; 1024];
+    loop {
+        let (_, client) = socket.recv_from(&mut buffer)?;
+        // Only a registration starts a replay, ignore entry list and track
+        // data requests, those are part of the recording.
+        if buffer[0] != 1 {
+            continue;
+        }
+        println!("Replaying {} packets to {client}", packets.len());
+        for packet in &packets {
+            socket.send_to(packet, client)?;
+            thread::sleep(interval);
+        }
+        println!("Done");
+    }
+}
@@ -0,0 +1,12 @@
+CREATE TABLE competitions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    track TEXT NOT NULL,
+    -- NULL means all car groups compete together
+    car_group TEXT,
+    start_timestamp INTEGER NOT NULL,
+    end_timestamp INTEGER NOT NULL,
+    CHECK (start_timestamp < end_timestamp)
+);
+
+CREATE INDEX sessions_timestamp_idx ON sessions(timestamp);
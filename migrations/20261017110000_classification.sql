@@ -0,0 +1,19 @@
+-- Classification from the leaderBoardLines of race and qualifying sessions.
+-- NULL for practice sessions, and for sessions imported before this existed.
+ALTER TABLE cars ADD COLUMN position INTEGER;
+ALTER TABLE cars ADD COLUMN lap_count INTEGER;
+ALTER TABLE cars ADD COLUMN total_time_ms INTEGER;
+ALTER TABLE cars ADD COLUMN best_lap_ms INTEGER;
+ALTER TABLE cars ADD COLUMN missing_mandatory_pitstop INTEGER;
+ALTER TABLE cars ADD COLUMN finishing_steam_id INTEGER REFERENCES drivers(steam_id);
+
+-- Time spent in the car per driver, in the order of the car's driver list
+CREATE TABLE car_drivers (
+    car_id INTEGER NOT NULL,
+    driver_index INTEGER NOT NULL,
+    steam_id INTEGER NOT NULL,
+    total_time_ms INTEGER NOT NULL,
+    FOREIGN KEY (car_id) REFERENCES cars(id) ON DELETE CASCADE,
+    FOREIGN KEY (steam_id) REFERENCES drivers(steam_id),
+    PRIMARY KEY (car_id, driver_index)
+);
@@ -0,0 +1,22 @@
+CREATE TABLE penalties (
+    id INTEGER PRIMARY KEY NOT NULL,
+    session_id INTEGER NOT NULL,
+    car_id INTEGER NOT NULL,
+    -- NULL if the driver index didn't match any of the car's drivers
+    steam_id INTEGER,
+    reason TEXT NOT NULL,
+    penalty TEXT NOT NULL,
+    penalty_value INTEGER NOT NULL,
+    violation_in_lap INTEGER NOT NULL,
+    cleared_in_lap INTEGER NOT NULL,
+    post_race INTEGER NOT NULL,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+    FOREIGN KEY (car_id) REFERENCES cars(id) ON DELETE CASCADE,
+    FOREIGN KEY (steam_id) REFERENCES drivers(steam_id)
+);
+
+CREATE INDEX penalties_session_id_idx ON penalties(session_id);
+CREATE INDEX penalties_steam_id_idx ON penalties(steam_id);
+
+-- Set on laps during which a penalty (drive through, stop and go) was served
+ALTER TABLE laps ADD COLUMN penalty_served INTEGER NOT NULL DEFAULT 0;
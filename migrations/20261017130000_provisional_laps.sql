@@ -0,0 +1,4 @@
+-- Laps that came in through live timing, and haven't been confirmed by a
+-- results file yet. The session they belong to is replaced as a whole once
+-- the results file comes in.
+ALTER TABLE laps ADD COLUMN provisional INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,83 @@
+-- Fastest valid lap and fastest valid sectors of each driver per track,
+-- conditions, car group and session type, kept up to date when laps are added
+-- so the boards don't have to go through all laps. Which session types count
+-- for the boards is configurable, the boards take the fastest of the session
+-- types that count. Everything is in here twice, once with and once without
+-- laps during which a penalty was served.
+CREATE TABLE personal_best_laps (
+    track TEXT NOT NULL,
+    wet INTEGER NOT NULL,
+    steam_id INTEGER NOT NULL,
+    car_group TEXT NOT NULL,
+    session_type TEXT NOT NULL,
+    exclude_penalty_laps INTEGER NOT NULL,
+    lap_id INTEGER NOT NULL,
+    time_ms INTEGER NOT NULL,
+    FOREIGN KEY (steam_id) REFERENCES drivers(steam_id),
+    FOREIGN KEY (lap_id) REFERENCES laps(id) ON DELETE CASCADE,
+    PRIMARY KEY (track, wet, steam_id, car_group, session_type, exclude_penalty_laps)
+);
+
+-- The sectors don't need to come from the same lap
+CREATE TABLE personal_best_sectors (
+    track TEXT NOT NULL,
+    wet INTEGER NOT NULL,
+    steam_id INTEGER NOT NULL,
+    car_group TEXT NOT NULL,
+    session_type TEXT NOT NULL,
+    exclude_penalty_laps INTEGER NOT NULL,
+    sector INTEGER NOT NULL,
+    time_ms INTEGER NOT NULL,
+    FOREIGN KEY (steam_id) REFERENCES drivers(steam_id),
+    PRIMARY KEY (track, wet, steam_id, car_group, session_type, exclude_penalty_laps, sector)
+);
+
+CREATE INDEX sessions_track_idx ON sessions(track);
+
+-- Backfill from the laps that are already there, the same way
+-- personal_bests::refresh does it for a single track.
+INSERT INTO personal_best_laps (
+    track, wet, steam_id, car_group, session_type, exclude_penalty_laps, lap_id, time_ms
+)
+SELECT track, wet, steam_id, car_group, session_type, exclude_penalty_laps, lap_id, time_ms
+FROM (
+    SELECT s.track,
+        s.wet,
+        l.steam_id,
+        c.car_group,
+        s.type AS session_type,
+        e.exclude_penalty_laps,
+        l.id AS lap_id,
+        l.time_ms,
+        ROW_NUMBER() OVER (
+            PARTITION BY s.track, s.wet, l.steam_id, c.car_group, s.type, e.exclude_penalty_laps
+            ORDER BY l.time_ms, s.timestamp, l.id
+        ) AS rank
+    FROM laps l
+    INNER JOIN sessions s ON l.session_id = s.id
+    INNER JOIN cars c ON l.car_id = c.id
+    CROSS JOIN (SELECT 0 AS exclude_penalty_laps UNION ALL SELECT 1) e
+    WHERE l.valid = 1
+    AND (e.exclude_penalty_laps = 0 OR l.penalty_served = 0)
+)
+WHERE rank = 1;
+
+INSERT INTO personal_best_sectors (
+    track, wet, steam_id, car_group, session_type, exclude_penalty_laps, sector, time_ms
+)
+SELECT s.track,
+    s.wet,
+    l.steam_id,
+    c.car_group,
+    s.type,
+    e.exclude_penalty_laps,
+    sp.sector,
+    MIN(sp.time_ms)
+FROM splits sp
+INNER JOIN laps l ON sp.lap_id = l.id
+INNER JOIN sessions s ON l.session_id = s.id
+INNER JOIN cars c ON l.car_id = c.id
+CROSS JOIN (SELECT 0 AS exclude_penalty_laps UNION ALL SELECT 1) e
+WHERE l.valid = 1
+AND (e.exclude_penalty_laps = 0 OR l.penalty_served = 0)
+GROUP BY s.track, s.wet, l.steam_id, c.car_group, s.type, e.exclude_penalty_laps, sp.sector;
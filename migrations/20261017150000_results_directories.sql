@@ -0,0 +1,16 @@
+-- The label of the results directory a session was imported from, if it has
+-- one. NULL for live timing sessions and for sessions imported before labels
+-- existed.
+ALTER TABLE sessions ADD COLUMN server_label TEXT;
+
+-- Several results directories can have files with the same name, so they're
+-- told apart by directory. Files imported before that have an empty
+-- directory, and count as known in every directory.
+CREATE TABLE known_files_new (
+    directory TEXT NOT NULL,
+    path TEXT NOT NULL,
+    PRIMARY KEY (directory, path)
+);
+INSERT INTO known_files_new (directory, path) SELECT '', path FROM known_files;
+DROP TABLE known_files;
+ALTER TABLE known_files_new RENAME TO known_files;
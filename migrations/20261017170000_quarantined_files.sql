@@ -0,0 +1,14 @@
+-- Results and entrylist files that couldn't be parsed. They're skipped until
+-- their contents change or an admin retries them, and the row goes away once
+-- the file makes it into known_files.
+CREATE TABLE quarantined_files (
+    directory TEXT NOT NULL,
+    path TEXT NOT NULL,
+    -- SHA-256 of the file contents, hex encoded
+    hash TEXT NOT NULL,
+    error TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (directory, path)
+);
@@ -0,0 +1,11 @@
+-- What a file looked like when it was imported, so that files ACC writes
+-- again get imported again. Size and modification time are checked first,
+-- the SHA-256 of the contents (hex encoded) decides. Files imported before
+-- this have NULLs, and are imported again the next time they're looked at.
+ALTER TABLE known_files ADD COLUMN size INTEGER;
+-- Nanoseconds since the epoch
+ALTER TABLE known_files ADD COLUMN mtime INTEGER;
+ALTER TABLE known_files ADD COLUMN hash TEXT;
+-- The session that was imported from the file, which gets replaced when the
+-- file changes
+ALTER TABLE known_files ADD COLUMN session_id INTEGER REFERENCES sessions(id) ON DELETE SET NULL;
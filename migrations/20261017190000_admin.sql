@@ -0,0 +1,27 @@
+-- Logged in admins, by the SHA-256 (hex encoded) of their session cookie
+CREATE TABLE admin_sessions (
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+-- Sessions an admin deleted that came from a file. The session itself is gone,
+-- this keeps the file from being imported again, also by a reimport, until
+-- the session is restored.
+CREATE TABLE deleted_sessions (
+    directory TEXT NOT NULL,
+    path TEXT NOT NULL,
+    track TEXT NOT NULL,
+    type TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    server_name TEXT NOT NULL,
+    deleted_at INTEGER NOT NULL,
+    PRIMARY KEY (directory, path)
+);
+
+-- Drivers whose names and such were edited by an admin, imports leave them
+-- alone
+ALTER TABLE drivers ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;
+
+-- When a file was imported, NULL for files imported before this
+ALTER TABLE known_files ADD COLUMN imported_at INTEGER;
@@ -0,0 +1,48 @@
+-- Laps a moderator took off the boards. They're still shown on the session and
+-- driver pages, marked as excluded. Set from lap_exclusions on every import.
+ALTER TABLE laps ADD COLUMN excluded INTEGER NOT NULL DEFAULT 0;
+
+-- Which lap of its car this is, counting from 1. NULL for live timing laps.
+ALTER TABLE laps ADD COLUMN lap_number INTEGER;
+UPDATE laps SET lap_number = numbered.lap_number
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY car_id ORDER BY id) AS lap_number
+    FROM laps
+    WHERE provisional = 0
+) AS numbered
+WHERE laps.id = numbered.id;
+
+-- The laps that are excluded. Importing a file again gives its session and
+-- laps new ids, so laps are picked out by what stays the same: the session's
+-- timestamp and server name, the driver and the lap number.
+CREATE TABLE lap_exclusions (
+    session_timestamp INTEGER NOT NULL,
+    server_name TEXT NOT NULL,
+    steam_id INTEGER NOT NULL,
+    lap_number INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    PRIMARY KEY (session_timestamp, server_name, steam_id, lap_number)
+);
+
+-- Everything moderators did to laps. Laps are picked out the same way as in
+-- lap_exclusions, and whatever's needed to show an entry is kept here too, for
+-- when the session is gone.
+CREATE TABLE lap_moderation (
+    id INTEGER PRIMARY KEY NOT NULL,
+    session_timestamp INTEGER NOT NULL,
+    server_name TEXT NOT NULL,
+    steam_id INTEGER NOT NULL,
+    lap_number INTEGER NOT NULL,
+    track TEXT NOT NULL,
+    time_ms INTEGER NOT NULL,
+    -- 'exclude' or 'restore'
+    action TEXT NOT NULL,
+    reason TEXT,
+    -- Name given when logging in, or 'token' for the admin token
+    moderator TEXT NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+CREATE INDEX lap_moderation_steam_id ON lap_moderation (steam_id);
+
+-- Who's logged in, for the moderation log
+ALTER TABLE admin_sessions ADD COLUMN name TEXT NOT NULL DEFAULT 'admin';
@@ -0,0 +1,28 @@
+-- A second (or third) Steam account of a driver points at their main one.
+-- Boards, lap counts and the driver page count the laps of all of them as the
+-- main account's. Only main accounts have accounts pointing at them.
+ALTER TABLE drivers ADD COLUMN merged_into INTEGER REFERENCES drivers(steam_id);
+CREATE INDEX drivers_merged_into_idx ON drivers (merged_into);
+
+-- Every name a Steam account showed up with, so renames don't lose the old
+-- name. Seen times are session timestamps, not when the file was imported.
+CREATE TABLE driver_names (
+    steam_id INTEGER NOT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    short_name TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    FOREIGN KEY (steam_id) REFERENCES drivers(steam_id) ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX driver_names_name_idx
+    ON driver_names (steam_id, first_name, last_name, short_name);
+
+-- The names so far are all that's left of the ones before them
+INSERT INTO driver_names (steam_id, first_name, last_name, short_name, first_seen, last_seen)
+SELECT d.steam_id, d.first_name, d.last_name, d.short_name,
+    COALESCE(MIN(s.timestamp), 0), COALESCE(MAX(s.timestamp), 0)
+FROM drivers d
+LEFT JOIN laps l ON l.steam_id = d.steam_id
+LEFT JOIN sessions s ON l.session_id = s.id
+GROUP BY d.steam_id;
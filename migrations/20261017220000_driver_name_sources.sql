@@ -0,0 +1,7 @@
+-- Names from entry lists are kept too, apart from the ones from results files,
+-- since entry lists can be a lot older than the session they're used for.
+-- 'results' or 'entrylist'
+ALTER TABLE driver_names ADD COLUMN source TEXT NOT NULL DEFAULT 'results';
+DROP INDEX driver_names_name_idx;
+CREATE UNIQUE INDEX driver_names_name_idx
+    ON driver_names (steam_id, first_name, last_name, short_name, source);
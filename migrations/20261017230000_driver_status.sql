@@ -0,0 +1,5 @@
+-- Hidden and banned drivers are left off the boards, their laps are kept.
+-- 'active', 'hidden' or 'banned'
+ALTER TABLE drivers ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+-- Shown on the driver page, if there is one
+ALTER TABLE drivers ADD COLUMN status_reason TEXT;
@@ -0,0 +1,1572 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{
+    extract::{self, OriginalUri, Path, Query, Request},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+    Extension, Form,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use super::{
+    car_group_to_display_name, constant_time_eq, driver_display_name, format_duration,
+    nationality_to_flag, rootpage::get_car_groups, session_type_to_display_name,
+    track_id_to_display_name, State, NATIONALITY_TO_COUNTRY,
+};
+use crate::{
+    config, configured_dirs,
+    events::{Event, EventSender},
+    link_driver, main_account, personal_bests, remove_session, restore_session,
+    retry_quarantined_file, session_file, unlink_driver, FileOutcome,
+};
+
+const SESSION_COOKIE: &str = "admin_session";
+// How long a login lasts
+const SESSION_DAYS: i64 = 14;
+const SESSIONS_PER_PAGE: i64 = 50;
+const DRIVERS_PER_PAGE: i64 = 100;
+
+// Who's doing the moderating, as far as the moderation log is concerned
+#[derive(Clone)]
+pub(crate) struct Moderator(String);
+
+// Everything under /admin, other than logging in and out, needs a logged in
+// admin, or the admin token in the Authorization header. Without a password
+// or token configured there's no admin area at all.
+pub(super) async fn require_admin(
+    extract::State(state): extract::State<State>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(admin) = &config::get().admin else {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    };
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let token_ok = token.is_some_and(|token| {
+        admin
+            .token
+            .as_ref()
+            .is_some_and(|admin_token| constant_time_eq(token.as_bytes(), admin_token.as_bytes()))
+    });
+    let moderator = if token_ok {
+        Some("token".to_string())
+    } else {
+        logged_in_name(&state, request.headers()).await.unwrap()
+    };
+    if let Some(moderator) = moderator {
+        request.extensions_mut().insert(Moderator(moderator));
+        return next.run(request).await;
+    }
+    // Pages send people to the login page, forms just get told no
+    if request.method() == Method::GET {
+        Redirect::to(&login_url(request.uri())).into_response()
+    } else {
+        (StatusCode::UNAUTHORIZED, "401 Unauthorized").into_response()
+    }
+}
+
+// Relative, like all the other links, so that the boards can live anywhere
+// behind a reverse proxy. The URI is the one below /admin.
+fn login_url(uri: &Uri) -> String {
+    let depth = uri.path().matches('/').count().saturating_sub(1);
+    format!("{}login", "../".repeat(depth))
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+// The name the admin logged in with, if they're logged in
+async fn logged_in_name(state: &State, headers: &HeaderMap) -> Result<Option<String>> {
+    let Some(token) = session_token(headers) else {
+        return Ok(None);
+    };
+    let mut conn = state.0.pool.acquire().await?;
+    let token_hash = token_hash(token);
+    let now = Utc::now().timestamp();
+    Ok(sqlx::query!(
+        "SELECT name FROM admin_sessions WHERE token_hash = ? AND expires_at > ?;",
+        token_hash,
+        now
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|row| row.name))
+}
+
+#[derive(Template)]
+#[template(path = "admin_login.html")]
+struct LoginTemplate {
+    failed: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LoginForm {
+    // There's only the one password, the name is what shows up in the
+    // moderation log
+    name: String,
+    password: String,
+}
+
+// Both `/admin` and `/admin/` end up here, and the redirect is relative so
+// that it works wherever /admin ended up
+pub(crate) async fn index_handler(OriginalUri(uri): OriginalUri) -> impl IntoResponse {
+    if uri.path().ends_with('/') {
+        Redirect::to("status")
+    } else {
+        Redirect::to("admin/status")
+    }
+}
+
+// No Path, so that it defaults to wherever /admin ended up. SameSite keeps
+// other sites from posting the admin forms.
+fn session_cookie(token: &str, max_age: i64) -> String {
+    let mut cookie =
+        format!("{SESSION_COOKIE}={token}; Max-Age={max_age}; HttpOnly; SameSite=Strict");
+    if config::get()
+        .admin
+        .as_ref()
+        .is_some_and(|admin| admin.secure_cookie)
+    {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+pub(crate) async fn login_page_handler() -> Response {
+    if config::get().admin.is_none() {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    LoginTemplate { failed: false }.into_response()
+}
+
+// The password or the token both work, whichever is configured
+pub(crate) async fn login_handler(
+    extract::State(state): extract::State<State>,
+    Form(form): Form<LoginForm>,
+) -> Response {
+    let Some(admin) = &config::get().admin else {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    };
+    let matches = |secret: &Option<String>| {
+        secret
+            .as_ref()
+            .is_some_and(|secret| constant_time_eq(form.password.as_bytes(), secret.as_bytes()))
+    };
+    let name = form.name.trim();
+    if name.is_empty() || (!matches(&admin.password) && !matches(&admin.token)) {
+        warn!("Failed admin login");
+        // Makes guessing a lot slower
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        return (StatusCode::UNAUTHORIZED, LoginTemplate { failed: true }).into_response();
+    }
+    info!("Admin {} logged in", name);
+    let token = hex::encode(rand::random::<[u8; 32]>());
+    let token_hash = token_hash(&token);
+    let now = Utc::now().timestamp();
+    let max_age = SESSION_DAYS * 24 * 60 * 60;
+    let expires_at = now + max_age;
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    sqlx::query!("DELETE FROM admin_sessions WHERE expires_at <= ?;", now)
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "INSERT INTO admin_sessions (token_hash, created_at, expires_at, name)
+        VALUES (?, ?, ?, ?);",
+        token_hash,
+        now,
+        expires_at,
+        name
+    )
+    .execute(&mut *conn)
+    .await
+    .unwrap();
+    let cookie = session_cookie(&token, max_age);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("status")).into_response()
+}
+
+pub(crate) async fn logout_handler(
+    extract::State(state): extract::State<State>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(token) = session_token(&headers) {
+        let token_hash = token_hash(token);
+        let mut conn = state.0.pool.acquire().await.unwrap();
+        sqlx::query!(
+            "DELETE FROM admin_sessions WHERE token_hash = ?;",
+            token_hash
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    }
+    let cookie = session_cookie("", 0);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("login"))
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DirectoryStatus {
+    path: String,
+    label: Option<String>,
+    files: i64,
+    sessions: i64,
+    last_imported: Option<i64>,
+    quarantined: i64,
+    deleted: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct RecentFile {
+    directory: String,
+    path: String,
+    imported_at: Option<i64>,
+    session_id: Option<i64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct StatusDisplayData {
+    directories: Vec<DirectoryStatus>,
+    // Imported before directories were tracked
+    older_files: i64,
+    recent_files: Vec<RecentFile>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_status.html")]
+struct StatusTemplate {
+    display_data: StatusDisplayData,
+}
+
+pub(crate) async fn status_handler(
+    extract::State(state): extract::State<State>,
+) -> impl IntoResponse {
+    debug!("admin status page");
+    let display_data = get_status_display_data(&state).await.unwrap();
+    StatusTemplate { display_data }
+}
+
+pub(super) async fn get_status_display_data(state: &State) -> Result<StatusDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+    let mut directories = Vec::new();
+    for results_dir in configured_dirs()? {
+        let path = results_dir.key();
+        let files = sqlx::query!(
+            r#"
+            SELECT COUNT(1) AS "files!: i64",
+                COUNT(session_id) AS "sessions!: i64",
+                MAX(imported_at) AS "last_imported: i64"
+            FROM known_files
+            WHERE directory = ?;
+            "#,
+            path
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        let quarantined = sqlx::query!(
+            r#"SELECT COUNT(1) AS "count!: i64" FROM quarantined_files WHERE directory = ?;"#,
+            path
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .count;
+        let deleted = sqlx::query!(
+            r#"SELECT COUNT(1) AS "count!: i64" FROM deleted_sessions WHERE directory = ?;"#,
+            path
+        )
+        .fetch_one(&mut *conn)
+        .await?
+        .count;
+        directories.push(DirectoryStatus {
+            path,
+            label: results_dir.label,
+            files: files.files,
+            sessions: files.sessions,
+            last_imported: files.last_imported,
+            quarantined,
+            deleted,
+        });
+    }
+    let older_files =
+        sqlx::query!(r#"SELECT COUNT(1) AS "count!: i64" FROM known_files WHERE directory = '';"#)
+            .fetch_one(&mut *conn)
+            .await?
+            .count;
+    let recent_files = sqlx::query_as!(
+        RecentFile,
+        "SELECT directory, path, imported_at, session_id
+        FROM known_files
+        WHERE imported_at IS NOT NULL
+        ORDER BY imported_at DESC, path DESC
+        LIMIT 20;"
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(StatusDisplayData {
+        directories,
+        older_files,
+        recent_files,
+    })
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SessionLine {
+    id: i64,
+    track_name: String,
+    session_type: &'static str,
+    server: String,
+    timestamp: i64,
+    laps: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DeletedSession {
+    directory: String,
+    path: String,
+    track_name: String,
+    session_type: &'static str,
+    server_name: String,
+    timestamp: i64,
+    deleted_at: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SessionsDisplayData {
+    sessions: Vec<SessionLine>,
+    page: i64,
+    pages: i64,
+    deleted: Vec<DeletedSession>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PageQuery {
+    page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RestoreForm {
+    directory: String,
+    path: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_sessions.html")]
+struct SessionsTemplate {
+    display_data: SessionsDisplayData,
+}
+
+pub(crate) async fn sessions_handler(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<PageQuery>,
+) -> impl IntoResponse {
+    debug!("admin sessions page");
+    let display_data = get_sessions_display_data(&state, query.page.unwrap_or(1))
+        .await
+        .unwrap();
+    SessionsTemplate { display_data }
+}
+
+pub(crate) async fn restore_handler(
+    extract::State(state): extract::State<State>,
+    Form(form): Form<RestoreForm>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    match restore_session(&form.directory, &form.path, &mut conn, &state.0.events).await {
+        Ok(Some(outcome)) => info!("Restored {}: {:?}", form.path, outcome),
+        Ok(None) => return (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+        Err(e) => warn!("Failed to restore {}: {:?}", form.path, e),
+    }
+    Redirect::to("sessions").into_response()
+}
+
+pub(super) async fn get_sessions_display_data(
+    state: &State,
+    page: i64,
+) -> Result<SessionsDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+    let session_count = sqlx::query!(r#"SELECT COUNT(1) AS "count!: i64" FROM sessions;"#)
+        .fetch_one(&mut *conn)
+        .await?
+        .count;
+    let pages = ((session_count + SESSIONS_PER_PAGE - 1) / SESSIONS_PER_PAGE).max(1);
+    let page = page.clamp(1, pages);
+    let offset = (page - 1) * SESSIONS_PER_PAGE;
+    let sessions = sqlx::query!(
+        r#"
+        SELECT s.id,
+            s.track,
+            s.type AS session_type,
+            COALESCE(s.server_label, s.server_name) AS "server!: String",
+            s.timestamp,
+            (SELECT COUNT(1) FROM laps l WHERE l.session_id = s.id) AS "laps!: i64"
+        FROM sessions s
+        ORDER BY s.timestamp DESC
+        LIMIT ? OFFSET ?;
+        "#,
+        SESSIONS_PER_PAGE,
+        offset
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| SessionLine {
+        id: row.id,
+        track_name: track_id_to_display_name(&row.track),
+        session_type: session_type_to_display_name(&row.session_type),
+        server: row.server,
+        timestamp: row.timestamp,
+        laps: row.laps,
+    })
+    .collect();
+    let deleted = sqlx::query!(
+        "SELECT directory, path, track, type AS session_type, server_name, timestamp, deleted_at
+        FROM deleted_sessions
+        ORDER BY deleted_at DESC;"
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| DeletedSession {
+        directory: row.directory,
+        path: row.path,
+        track_name: track_id_to_display_name(&row.track),
+        session_type: session_type_to_display_name(&row.session_type),
+        server_name: row.server_name,
+        timestamp: row.timestamp,
+        deleted_at: row.deleted_at,
+    })
+    .collect();
+    Ok(SessionsDisplayData {
+        sessions,
+        page,
+        pages,
+        deleted,
+    })
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct LapLine {
+    id: i64,
+    steam_id: i64,
+    driver: String,
+    race_number: i64,
+    // What exclusions go by, live timing laps don't have one
+    lap_number: Option<i64>,
+    laptime: String,
+    valid: bool,
+    excluded: bool,
+    excluded_reason: Option<String>,
+    penalty_served: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SessionDisplayData {
+    session: SessionLine,
+    // Where the session came from, sessions without a file can't be restored
+    // once deleted
+    file: Option<String>,
+    laps: Vec<LapLine>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SessionAction {
+    Delete,
+    ExcludeLap,
+    RestoreLap,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SessionForm {
+    action: SessionAction,
+    lap_id: Option<i64>,
+    reason: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_session.html")]
+struct SessionTemplate {
+    display_data: SessionDisplayData,
+}
+
+pub(crate) async fn session_handler(
+    extract::State(state): extract::State<State>,
+    Path(session_id): Path<i64>,
+) -> Response {
+    debug!("admin session page for {}", session_id);
+    match get_session_display_data(&state, session_id).await.unwrap() {
+        Some(display_data) => SessionTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+pub(crate) async fn session_action_handler(
+    extract::State(state): extract::State<State>,
+    Path(session_id): Path<i64>,
+    Extension(Moderator(moderator)): Extension<Moderator>,
+    Form(form): Form<SessionForm>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let reason = form
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+    let (lap_id, excluded) = match (form.action, form.lap_id) {
+        (SessionAction::Delete, _) => {
+            if !remove_session(session_id, &mut conn).await.unwrap() {
+                return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+            }
+            // Also tells the SSE clients, and drops the cached pages
+            let _ = state.0.events.send(Event::SessionDeleted { session_id });
+            return Redirect::to("../sessions").into_response();
+        }
+        // Taking a lap off the boards needs a reason, putting it back doesn't
+        (SessionAction::ExcludeLap, Some(lap_id)) if reason.is_some() => (lap_id, true),
+        (SessionAction::RestoreLap, Some(lap_id)) => (lap_id, false),
+        _ => return (StatusCode::BAD_REQUEST, "400 Bad Request").into_response(),
+    };
+    match set_lap_excluded(
+        &mut conn,
+        &state.0.events,
+        session_id,
+        lap_id,
+        excluded,
+        reason,
+        &moderator,
+    )
+    .await
+    .unwrap()
+    {
+        LapModeration::Done => {}
+        LapModeration::NotFound => {
+            return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+        }
+        LapModeration::Provisional => {
+            return (
+                StatusCode::CONFLICT,
+                "409 Conflict: live timing laps can't be excluded, wait for the results file",
+            )
+                .into_response();
+        }
+    }
+    Redirect::to(&session_id.to_string()).into_response()
+}
+
+enum LapModeration {
+    Done,
+    NotFound,
+    // Live timing laps have no lap number for the exclusion to go by, the
+    // results file replaces them anyway
+    Provisional,
+}
+
+// Takes a lap off the boards, or puts it back, and keeps track of who did it
+// and why. The event also drops the cached pages.
+async fn set_lap_excluded(
+    conn: &mut sqlx::SqliteConnection,
+    events: &EventSender,
+    session_id: i64,
+    lap_id: i64,
+    excluded: bool,
+    reason: Option<&str>,
+    moderator: &str,
+) -> Result<LapModeration> {
+    let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+    let Some(lap) = sqlx::query!(
+        r#"SELECT steam_id, time_ms, lap_number, excluded AS "excluded: bool"
+        FROM laps
+        WHERE id = ? AND session_id = ?;"#,
+        lap_id,
+        session_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(LapModeration::NotFound);
+    };
+    let Some(lap_number) = lap.lap_number else {
+        return Ok(LapModeration::Provisional);
+    };
+    // Already done, by someone else or by submitting the form twice
+    if lap.excluded == excluded {
+        return Ok(LapModeration::Done);
+    }
+    sqlx::query!(
+        "UPDATE laps SET excluded = ? WHERE id = ?;",
+        excluded,
+        lap_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    let session = sqlx::query!(
+        r#"SELECT track, wet AS "wet: bool", timestamp, server_name
+        FROM sessions
+        WHERE id = ?;"#,
+        session_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    // Kept apart from the lap, so that it's excluded again when the file is
+    // imported again
+    if excluded {
+        sqlx::query!(
+            "INSERT INTO lap_exclusions (
+                session_timestamp, server_name, steam_id, lap_number, reason
+            ) VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT DO UPDATE SET reason = excluded.reason;",
+            session.timestamp,
+            session.server_name,
+            lap.steam_id,
+            lap_number,
+            reason
+        )
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        sqlx::query!(
+            "DELETE FROM lap_exclusions
+            WHERE session_timestamp = ? AND server_name = ? AND steam_id = ? AND lap_number = ?;",
+            session.timestamp,
+            session.server_name,
+            lap.steam_id,
+            lap_number
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    let action = if excluded { "exclude" } else { "restore" };
+    let now = Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO lap_moderation (
+            session_timestamp, server_name, steam_id, lap_number, track, time_ms, action,
+            reason, moderator, timestamp
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        session.timestamp,
+        session.server_name,
+        lap.steam_id,
+        lap_number,
+        session.track,
+        lap.time_ms,
+        action,
+        reason,
+        moderator,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+    personal_bests::refresh(&mut tx, &session.track, Some(lap.steam_id)).await?;
+    tx.commit().await?;
+    info!("Lap {} {}d by {}", lap_id, action, moderator);
+    // Nobody listening is fine
+    let _ = events.send(Event::LapModerated {
+        steam_id: lap.steam_id,
+        session_id,
+        lap_id,
+        track: session.track,
+        wet: session.wet,
+        laptime_ms: lap.time_ms,
+        excluded,
+    });
+    Ok(LapModeration::Done)
+}
+
+pub(super) async fn get_session_display_data(
+    state: &State,
+    session_id: i64,
+) -> Result<Option<SessionDisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT s.id,
+            s.track,
+            s.type AS session_type,
+            COALESCE(s.server_label, s.server_name) AS "server!: String",
+            s.timestamp,
+            (SELECT COUNT(1) FROM laps l WHERE l.session_id = s.id) AS "laps!: i64"
+        FROM sessions s
+        WHERE s.id = ?;
+        "#,
+        session_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let file = session_file(session_id, row.timestamp, &row.session_type, &mut conn)
+        .await?
+        .map(|(directory, path)| format!("{directory}/{path}"));
+    let session = SessionLine {
+        id: row.id,
+        track_name: track_id_to_display_name(&row.track),
+        session_type: session_type_to_display_name(&row.session_type),
+        server: row.server,
+        timestamp: row.timestamp,
+        laps: row.laps,
+    };
+    let laps = sqlx::query!(
+        r#"
+        SELECT l.id,
+            l.steam_id,
+            d.first_name,
+            d.last_name,
+            d.short_name,
+            c.race_number,
+            l.lap_number,
+            l.time_ms,
+            l.valid AS "valid: bool",
+            l.excluded AS "excluded: bool",
+            (SELECT e.reason
+             FROM lap_exclusions e
+             INNER JOIN sessions s ON e.session_timestamp = s.timestamp
+                AND e.server_name = s.server_name
+             WHERE s.id = l.session_id
+             AND e.steam_id = l.steam_id
+             AND e.lap_number = l.lap_number
+             AND l.excluded = 1
+            ) AS "excluded_reason?: String",
+            l.penalty_served AS "penalty_served: bool"
+        FROM laps l
+        INNER JOIN drivers d ON l.steam_id = d.steam_id
+        INNER JOIN cars c ON l.car_id = c.id
+        WHERE l.session_id = ?
+        ORDER BY c.race_number, l.id;
+        "#,
+        session_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| LapLine {
+        id: row.id,
+        steam_id: row.steam_id,
+        driver: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+        race_number: row.race_number,
+        lap_number: row.lap_number,
+        laptime: format_duration(Duration::from_millis(row.time_ms.try_into().unwrap())),
+        valid: row.valid,
+        excluded: row.excluded,
+        excluded_reason: row.excluded_reason,
+        penalty_served: row.penalty_served,
+    })
+    .collect();
+    Ok(Some(SessionDisplayData {
+        session,
+        file,
+        laps,
+    }))
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DriverLine {
+    steam_id: i64,
+    name: String,
+    nickname: Option<String>,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    locked: bool,
+    merged_into: Option<i64>,
+    status: String,
+    laps: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DriversDisplayData {
+    drivers: Vec<DriverLine>,
+    search: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DriversQuery {
+    #[serde(default)]
+    search: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin_drivers.html")]
+struct DriversTemplate {
+    display_data: DriversDisplayData,
+}
+
+pub(crate) async fn drivers_handler(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<DriversQuery>,
+) -> impl IntoResponse {
+    debug!("admin drivers page, search {:?}", query.search);
+    let display_data = get_drivers_display_data(&state, query.search)
+        .await
+        .unwrap();
+    DriversTemplate { display_data }
+}
+
+pub(super) async fn get_drivers_display_data(
+    state: &State,
+    search: String,
+) -> Result<DriversDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+    let pattern = format!("%{}%", search.trim());
+    let drivers = sqlx::query!(
+        r#"
+        SELECT d.steam_id,
+            d.first_name,
+            d.last_name,
+            d.short_name,
+            d.nickname,
+            d.nationality,
+            d.locked AS "locked: bool",
+            d.merged_into,
+            d.status,
+            (SELECT COUNT(1) FROM laps l WHERE l.steam_id = d.steam_id) AS "laps!: i64"
+        FROM drivers d
+        WHERE d.first_name || ' ' || d.last_name LIKE ?1
+        OR d.short_name LIKE ?1
+        OR d.nickname LIKE ?1
+        OR CAST(d.steam_id AS TEXT) LIKE ?1
+        ORDER BY d.last_name, d.first_name
+        LIMIT ?2;
+        "#,
+        pattern,
+        DRIVERS_PER_PAGE
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let (flag_code, flag_name) = nationality_to_flag(row.nationality);
+        DriverLine {
+            steam_id: row.steam_id,
+            name: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+            nickname: row.nickname,
+            flag_code,
+            flag_name,
+            locked: row.locked,
+            merged_into: row.merged_into,
+            status: row.status,
+            laps: row.laps,
+        }
+    })
+    .collect();
+    Ok(DriversDisplayData { drivers, search })
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DriverDisplayData {
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nickname: String,
+    locked: bool,
+    // Nationality, country and whether it's the driver's
+    nationalities: Vec<(i64, &'static str, bool)>,
+    // The main account this one is linked to, or the ones linked to this one
+    merged_into: Option<LinkedAccount>,
+    accounts: Vec<LinkedAccount>,
+    // Status, what to call it and whether it's the driver's
+    statuses: Vec<(&'static str, &'static str, bool)>,
+    status_reason: String,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct LinkedAccount {
+    steam_id: i64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LinkForm {
+    main_steam_id: i64,
+}
+
+// Hidden and banned drivers are both left off the boards, banned ones get
+// called out as such on their page
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DriverStatus {
+    Active,
+    Hidden,
+    Banned,
+}
+
+impl DriverStatus {
+    const ALL: [Self; 3] = [Self::Active, Self::Hidden, Self::Banned];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Hidden => "hidden",
+            Self::Banned => "banned",
+        }
+    }
+
+    fn display_name(self) -> &'static str {
+        match self {
+            Self::Active => "Active",
+            Self::Hidden => "Hidden",
+            Self::Banned => "Banned",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct StatusForm {
+    status: DriverStatus,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DriverForm {
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nickname: String,
+    nationality: String,
+    // Checkboxes are only sent when checked
+    locked: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_driver.html")]
+struct DriverTemplate {
+    display_data: DriverDisplayData,
+}
+
+pub(crate) async fn driver_handler(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+) -> Response {
+    debug!("admin driver page for {}", steam_id);
+    match get_driver_display_data(&state, steam_id).await.unwrap() {
+        Some(display_data) => DriverTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+// Edited drivers are locked by default, otherwise the next results file with
+// them in it would undo the edit
+pub(crate) async fn driver_edit_handler(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+    Form(form): Form<DriverForm>,
+) -> Response {
+    let nickname = Some(form.nickname.trim()).filter(|nickname| !nickname.is_empty());
+    let nationality = form.nationality.parse::<i64>().ok();
+    let locked = form.locked.is_some();
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let updated = sqlx::query!(
+        "UPDATE drivers SET
+            first_name = ?,
+            last_name = ?,
+            short_name = ?,
+            nickname = ?,
+            nationality = ?,
+            locked = ?
+        WHERE steam_id = ?;",
+        form.first_name,
+        form.last_name,
+        form.short_name,
+        nickname,
+        nationality,
+        locked,
+        steam_id
+    )
+    .execute(&mut *conn)
+    .await
+    .unwrap()
+    .rows_affected();
+    if updated == 0 {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    info!("Driver {} edited", steam_id);
+    let _ = state.0.events.send(Event::DriversChanged {
+        steam_ids: vec![steam_id],
+    });
+    Redirect::to(&steam_id.to_string()).into_response()
+}
+
+// A second Steam account of a driver, whose laps count as the main account's
+pub(crate) async fn driver_link_handler(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+    Form(form): Form<LinkForm>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    if !link_driver(steam_id, form.main_steam_id, &mut conn)
+        .await
+        .unwrap()
+    {
+        return (StatusCode::BAD_REQUEST, "400 Bad Request").into_response();
+    }
+    let _ = state.0.events.send(Event::DriversChanged {
+        steam_ids: vec![steam_id, form.main_steam_id],
+    });
+    Redirect::to(&format!("../{steam_id}")).into_response()
+}
+
+// Takes a driver off the boards or puts them back. Linked accounts go along
+// with their main account, and get the same status so that they keep it when
+// they're unlinked.
+pub(crate) async fn driver_status_handler(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+    Extension(Moderator(moderator)): Extension<Moderator>,
+    Form(form): Form<StatusForm>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let main_steam_id = main_account(&mut conn, steam_id).await.unwrap();
+    let status = form.status.as_str();
+    // Active drivers have nothing to explain
+    let reason = form
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty() && !matches!(form.status, DriverStatus::Active));
+    let mut tx = sqlx::Connection::begin(&mut *conn).await.unwrap();
+    let updated = sqlx::query!(
+        "UPDATE drivers SET status = ?1, status_reason = ?2
+        WHERE steam_id = ?3 OR merged_into = ?3;",
+        status,
+        reason,
+        main_steam_id
+    )
+    .execute(&mut *tx)
+    .await
+    .unwrap()
+    .rows_affected();
+    if updated == 0 {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    personal_bests::refresh_driver(&mut tx, main_steam_id)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    info!(
+        "Driver {} set to {} by {}",
+        main_steam_id, status, moderator
+    );
+    let _ = state.0.events.send(Event::DriversChanged {
+        steam_ids: vec![main_steam_id],
+    });
+    Redirect::to(&format!("../{steam_id}")).into_response()
+}
+
+pub(crate) async fn driver_unlink_handler(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let main_steam_id = main_account(&mut conn, steam_id).await.unwrap();
+    if !unlink_driver(steam_id, &mut conn).await.unwrap() {
+        return (StatusCode::BAD_REQUEST, "400 Bad Request").into_response();
+    }
+    let _ = state.0.events.send(Event::DriversChanged {
+        steam_ids: vec![steam_id, main_steam_id],
+    });
+    Redirect::to(&format!("../{steam_id}")).into_response()
+}
+
+pub(super) async fn get_driver_display_data(
+    state: &State,
+    steam_id: i64,
+) -> Result<Option<DriverDisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT first_name,
+            last_name,
+            short_name,
+            nickname,
+            nationality,
+            locked AS "locked: bool",
+            merged_into,
+            status,
+            status_reason
+        FROM drivers
+        WHERE steam_id = ?;
+        "#,
+        steam_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let mut nationalities = NATIONALITY_TO_COUNTRY
+        .entries()
+        .map(|(nationality, country)| {
+            (
+                *nationality,
+                *country,
+                row.nationality == Some(*nationality),
+            )
+        })
+        .collect::<Vec<_>>();
+    nationalities.sort_unstable_by_key(|(_, country, _)| *country);
+    let linked = sqlx::query!(
+        "SELECT steam_id, first_name, last_name, short_name
+        FROM drivers
+        WHERE steam_id = ?1 OR merged_into = ?2
+        ORDER BY steam_id;",
+        row.merged_into,
+        steam_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|linked| LinkedAccount {
+        steam_id: linked.steam_id,
+        name: driver_display_name(&linked.first_name, &linked.last_name, &linked.short_name),
+    })
+    .collect::<Vec<_>>();
+    let (merged_into, accounts) = if row.merged_into.is_some() {
+        (linked.into_iter().next(), Vec::new())
+    } else {
+        (None, linked)
+    };
+    Ok(Some(DriverDisplayData {
+        steam_id,
+        first_name: row.first_name,
+        last_name: row.last_name,
+        short_name: row.short_name,
+        nickname: row.nickname.unwrap_or_default(),
+        locked: row.locked,
+        nationalities,
+        merged_into,
+        accounts,
+        statuses: DriverStatus::ALL
+            .into_iter()
+            .map(|status| {
+                (
+                    status.as_str(),
+                    status.display_name(),
+                    row.status == status.as_str(),
+                )
+            })
+            .collect(),
+        status_reason: row.status_reason.unwrap_or_default(),
+    }))
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct CompetitionLine {
+    id: i64,
+    name: String,
+    track: String,
+    track_name: String,
+    class_name: Option<String>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+    // The same times the way datetime-local inputs want them, in UTC
+    start: String,
+    end: String,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct CompetitionsDisplayData {
+    competitions: Vec<CompetitionLine>,
+    // Track and what to call it, for the tracks that have sessions
+    tracks: Vec<(String, String)>,
+    // Car group, what to call it and whether it's the competition's
+    classes: Vec<(String, String, bool)>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct CompetitionDisplayData {
+    competition: CompetitionLine,
+    tracks: Vec<(String, String)>,
+    classes: Vec<(String, String, bool)>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CompetitionForm {
+    name: String,
+    track: String,
+    // Empty for all car groups together
+    class: String,
+    start: String,
+    end: String,
+}
+
+struct ValidCompetition<'a> {
+    name: &'a str,
+    track: &'a str,
+    car_group: Option<&'a str>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+impl CompetitionForm {
+    // Everything but the class is needed, and a competition has to end after
+    // it starts
+    fn validate(&self) -> Option<ValidCompetition<'_>> {
+        let name = Some(self.name.trim()).filter(|name| !name.is_empty())?;
+        let track = Some(self.track.trim()).filter(|track| !track.is_empty())?;
+        let car_group = Some(self.class.trim()).filter(|class| !class.is_empty());
+        let start_timestamp = parse_utc_datetime(&self.start)?;
+        let end_timestamp = parse_utc_datetime(&self.end)?;
+        (start_timestamp < end_timestamp).then_some(ValidCompetition {
+            name,
+            track,
+            car_group,
+            start_timestamp,
+            end_timestamp,
+        })
+    }
+}
+
+// datetime-local inputs send minutes, and seconds if they were given
+fn parse_utc_datetime(value: &str) -> Option<i64> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .map(|datetime| datetime.and_utc().timestamp())
+}
+
+fn format_utc_datetime(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|datetime| datetime.format("%Y-%m-%dT%H:%M").to_string())
+        .unwrap_or_default()
+}
+
+#[derive(Template)]
+#[template(path = "admin_competitions.html")]
+struct CompetitionsTemplate {
+    display_data: CompetitionsDisplayData,
+}
+
+#[derive(Template)]
+#[template(path = "admin_competition.html")]
+struct CompetitionTemplate {
+    display_data: CompetitionDisplayData,
+}
+
+pub(crate) async fn competitions_handler(
+    extract::State(state): extract::State<State>,
+) -> impl IntoResponse {
+    debug!("admin competitions page");
+    let display_data = get_competitions_display_data(&state).await.unwrap();
+    CompetitionsTemplate { display_data }
+}
+
+pub(crate) async fn competition_create_handler(
+    extract::State(state): extract::State<State>,
+    Extension(Moderator(moderator)): Extension<Moderator>,
+    Form(form): Form<CompetitionForm>,
+) -> Response {
+    let Some(competition) = form.validate() else {
+        return (StatusCode::BAD_REQUEST, "400 Bad Request").into_response();
+    };
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let id = sqlx::query!(
+        "INSERT INTO competitions (name, track, car_group, start_timestamp, end_timestamp)
+        VALUES (?, ?, ?, ?, ?)
+        RETURNING id;",
+        competition.name,
+        competition.track,
+        competition.car_group,
+        competition.start_timestamp,
+        competition.end_timestamp
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .unwrap()
+    .id;
+    info!("Competition {} created by {}", id, moderator);
+    Redirect::to("competitions").into_response()
+}
+
+pub(crate) async fn competition_handler(
+    extract::State(state): extract::State<State>,
+    Path(competition_id): Path<i64>,
+) -> Response {
+    debug!("admin competition page for {}", competition_id);
+    match get_competition_display_data(&state, competition_id)
+        .await
+        .unwrap()
+    {
+        Some(display_data) => CompetitionTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+pub(crate) async fn competition_edit_handler(
+    extract::State(state): extract::State<State>,
+    Path(competition_id): Path<i64>,
+    Extension(Moderator(moderator)): Extension<Moderator>,
+    Form(form): Form<CompetitionForm>,
+) -> Response {
+    let Some(competition) = form.validate() else {
+        return (StatusCode::BAD_REQUEST, "400 Bad Request").into_response();
+    };
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let updated = sqlx::query!(
+        "UPDATE competitions SET
+            name = ?,
+            track = ?,
+            car_group = ?,
+            start_timestamp = ?,
+            end_timestamp = ?
+        WHERE id = ?;",
+        competition.name,
+        competition.track,
+        competition.car_group,
+        competition.start_timestamp,
+        competition.end_timestamp,
+        competition_id
+    )
+    .execute(&mut *conn)
+    .await
+    .unwrap()
+    .rows_affected();
+    if updated == 0 {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    info!("Competition {} edited by {}", competition_id, moderator);
+    Redirect::to(&competition_id.to_string()).into_response()
+}
+
+pub(crate) async fn competition_delete_handler(
+    extract::State(state): extract::State<State>,
+    Path(competition_id): Path<i64>,
+    Extension(Moderator(moderator)): Extension<Moderator>,
+) -> Response {
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let deleted = sqlx::query!("DELETE FROM competitions WHERE id = ?;", competition_id)
+        .execute(&mut *conn)
+        .await
+        .unwrap()
+        .rows_affected();
+    if deleted == 0 {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    info!("Competition {} deleted by {}", competition_id, moderator);
+    Redirect::to("../competitions").into_response()
+}
+
+struct CompetitionQueryRow {
+    id: i64,
+    name: String,
+    track: String,
+    car_group: Option<String>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+impl CompetitionLine {
+    fn from_row(row: CompetitionQueryRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            track_name: track_id_to_display_name(&row.track),
+            track: row.track,
+            class_name: row.car_group.as_deref().map(car_group_to_display_name),
+            start_timestamp: row.start_timestamp,
+            end_timestamp: row.end_timestamp,
+            start: format_utc_datetime(row.start_timestamp),
+            end: format_utc_datetime(row.end_timestamp),
+        }
+    }
+}
+
+async fn get_competition_tracks(
+    conn: &mut sqlx::SqliteConnection,
+) -> Result<Vec<(String, String)>> {
+    Ok(
+        sqlx::query!("SELECT DISTINCT track FROM sessions ORDER BY track;")
+            .fetch_all(conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let track_name = track_id_to_display_name(&row.track);
+                (row.track, track_name)
+            })
+            .collect(),
+    )
+}
+
+// The competition's car group is in there even if nobody drove it yet
+async fn get_competition_classes(
+    state: &State,
+    car_group: Option<&str>,
+) -> Result<Vec<(String, String, bool)>> {
+    let mut car_groups = get_car_groups(state).await?;
+    if let Some(car_group) = car_group {
+        if !car_groups.iter().any(|known| known == car_group) {
+            car_groups.push(car_group.to_string());
+        }
+    }
+    Ok(car_groups
+        .into_iter()
+        .map(|class| {
+            let class_name = car_group_to_display_name(&class);
+            let selected = car_group == Some(class.as_str());
+            (class, class_name, selected)
+        })
+        .collect())
+}
+
+pub(super) async fn get_competitions_display_data(
+    state: &State,
+) -> Result<CompetitionsDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+    let competitions = sqlx::query_as!(
+        CompetitionQueryRow,
+        "SELECT id, name, track, car_group, start_timestamp, end_timestamp
+        FROM competitions
+        ORDER BY start_timestamp DESC, id DESC;"
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(CompetitionLine::from_row)
+    .collect();
+    Ok(CompetitionsDisplayData {
+        competitions,
+        tracks: get_competition_tracks(&mut conn).await?,
+        classes: get_competition_classes(state, None).await?,
+    })
+}
+
+pub(super) async fn get_competition_display_data(
+    state: &State,
+    competition_id: i64,
+) -> Result<Option<CompetitionDisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+    let Some(row) = sqlx::query_as!(
+        CompetitionQueryRow,
+        "SELECT id, name, track, car_group, start_timestamp, end_timestamp
+        FROM competitions
+        WHERE id = ?;",
+        competition_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let classes = get_competition_classes(state, row.car_group.as_deref()).await?;
+    Ok(Some(CompetitionDisplayData {
+        competition: CompetitionLine::from_row(row),
+        tracks: get_competition_tracks(&mut conn).await?,
+        classes,
+    }))
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct QuarantinedFile {
+    directory: String,
+    path: String,
+    hash: String,
+    error: String,
+    first_seen: i64,
+    last_seen: i64,
+    attempts: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct QuarantineDisplayData {
+    files: Vec<QuarantinedFile>,
+    retry_result: Option<RetryResult>,
+}
+
+// Where a retry ended up, passed along in the redirect back to the page
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct RetryResult {
+    retried: usize,
+    ingested: usize,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RetryForm {
+    directory: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_quarantine.html")]
+struct QuarantineTemplate {
+    display_data: QuarantineDisplayData,
+}
+
+pub(crate) async fn quarantine_handler(
+    extract::State(state): extract::State<State>,
+    retry_result: Option<Query<RetryResult>>,
+) -> impl IntoResponse {
+    debug!("admin quarantine page");
+    let display_data = QuarantineDisplayData {
+        files: get_quarantined_files(&state).await.unwrap(),
+        retry_result: retry_result.map(|Query(retry_result)| retry_result),
+    };
+    QuarantineTemplate { display_data }
+}
+
+// Retries one file, or all of them without one in the form
+pub(crate) async fn retry_handler(
+    extract::State(state): extract::State<State>,
+    Form(form): Form<RetryForm>,
+) -> impl IntoResponse {
+    let files = get_quarantined_files(&state)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|file| {
+            form.directory
+                .as_ref()
+                .is_none_or(|directory| *directory == file.directory)
+                && form.path.as_ref().is_none_or(|path| *path == file.path)
+        })
+        .collect::<Vec<_>>();
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let mut ingested = 0;
+    for file in &files {
+        info!("Retrying quarantined file {}", file.path);
+        match retry_quarantined_file(&file.directory, &file.path, &mut conn, &state.0.events).await
+        {
+            Ok(FileOutcome::Unreadable { .. }) => {}
+            Ok(_) => ingested += 1,
+            Err(e) => warn!("Failed to retry {}: {:?}", file.path, e),
+        }
+    }
+    let retry_result = RetryResult {
+        retried: files.len(),
+        ingested,
+    };
+    Redirect::to(&format!(
+        "quarantine?{}",
+        serde_urlencoded::to_string(retry_result).unwrap()
+    ))
+}
+
+pub(super) async fn get_quarantined_files(state: &State) -> Result<Vec<QuarantinedFile>> {
+    let mut conn = state.0.pool.acquire().await?;
+    Ok(sqlx::query_as!(
+        QuarantinedFile,
+        "SELECT directory, path, hash, error, first_seen, last_seen, attempts
+        FROM quarantined_files
+        ORDER BY last_seen DESC, path;"
+    )
+    .fetch_all(&mut *conn)
+    .await?)
+}
@@ -0,0 +1,270 @@
+use axum::{
+    extract,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    car_group_to_display_name, car_model_to_display_name, cars, compare, competition, driver,
+    rootpage, session, track, BoardFilter, DurationWithClass, State,
+};
+
+// Everything in here returns the same data the HTML pages are rendered from,
+// just serialized as JSON. That way the bot and overlays see exactly what the
+// website shows, including the purple/green classes.
+
+pub(crate) enum ApiError {
+    NotFound,
+    Unauthorized,
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            Self::NotFound => (StatusCode::NOT_FOUND, "Not found".to_string()),
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            Self::BadRequest(error) => (StatusCode::BAD_REQUEST, error),
+            Self::Internal(e) => {
+                error!("API error: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Internal server error".to_string(),
+                )
+            }
+        };
+        (status, Json(ErrorBody { error })).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Serialize)]
+pub(crate) struct CarClass {
+    id: String,
+    name: String,
+}
+
+pub(crate) async fn classes(
+    extract::State(state): extract::State<State>,
+) -> ApiResult<Vec<CarClass>> {
+    debug!("API: classes");
+    let classes = rootpage::get_car_groups(&state)
+        .await?
+        .into_iter()
+        .map(|car_group| CarClass {
+            name: car_group_to_display_name(&car_group),
+            id: car_group,
+        })
+        .collect();
+    Ok(Json(classes))
+}
+
+#[derive(Serialize)]
+pub(crate) struct CarModel {
+    id: i64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ModelsQuery {
+    class: Option<String>,
+}
+
+pub(crate) async fn models(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<ModelsQuery>,
+) -> ApiResult<Vec<CarModel>> {
+    debug!("API: models, class {:?}", query.class);
+    let models = rootpage::get_car_models(&state, query.class.as_deref())
+        .await?
+        .into_iter()
+        .map(|model| CarModel {
+            id: model,
+            name: car_model_to_display_name(model),
+        })
+        .collect();
+    Ok(Json(models))
+}
+
+pub(crate) async fn servers(
+    extract::State(state): extract::State<State>,
+) -> ApiResult<Vec<String>> {
+    debug!("API: servers");
+    Ok(Json(rootpage::get_servers(&state).await?))
+}
+
+pub(crate) async fn fastest_per_car(
+    extract::State(state): extract::State<State>,
+) -> ApiResult<cars::DisplayData> {
+    debug!("API: cars");
+    Ok(Json(cars::get_display_data(state).await?))
+}
+
+pub(crate) async fn compare(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<compare::CompareQuery>,
+) -> ApiResult<compare::DisplayData> {
+    debug!("API: compare, query {:?}", query);
+    compare::get_display_data(state, query)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+pub(crate) async fn competitions(
+    extract::State(state): extract::State<State>,
+) -> ApiResult<competition::IndexDisplayData> {
+    debug!("API: competitions");
+    Ok(Json(competition::get_index_display_data(state).await?))
+}
+
+pub(crate) async fn competition(
+    extract::State(state): extract::State<State>,
+    Path(competition_id): Path<i64>,
+) -> ApiResult<competition::DisplayData> {
+    debug!("API: competition {}", competition_id);
+    competition::get_display_data(state, competition_id)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+pub(crate) async fn sessions(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<session::SessionsQuery>,
+) -> ApiResult<session::IndexDisplayData> {
+    debug!("API: sessions, query {:?}", query);
+    Ok(Json(session::get_index_display_data(state, query).await?))
+}
+
+pub(crate) async fn session(
+    extract::State(state): extract::State<State>,
+    Path(session_id): Path<i64>,
+) -> ApiResult<session::DisplayData> {
+    debug!("API: session {}", session_id);
+    session::get_display_data(state, session_id)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+#[derive(Serialize)]
+pub(crate) struct TrackSummary {
+    id: String,
+    name: String,
+    wet: bool,
+    overall_optimal_laptime: DurationWithClass,
+    fastest_laptime: Option<DurationWithClass>,
+    drivers: usize,
+    last_timestamp: Option<i64>,
+}
+
+pub(crate) async fn tracks(
+    extract::State(state): extract::State<State>,
+    Query(filter): Query<BoardFilter>,
+) -> ApiResult<Vec<TrackSummary>> {
+    debug!("API: tracks, filter {:?}", filter);
+    let display_data = rootpage::get_display_data(state, filter).await?;
+    let summaries = display_data
+        .into_iter()
+        .map(|track_data| TrackSummary {
+            fastest_laptime: track_data
+                .display_lines
+                .first()
+                .map(|line| line.laptime.clone()),
+            drivers: track_data.display_lines.len(),
+            last_timestamp: track_data
+                .display_lines
+                .iter()
+                .map(|line| line.timestamp)
+                .max(),
+            id: track_data.id,
+            name: track_data.name,
+            wet: track_data.wet,
+            overall_optimal_laptime: track_data.overall_optimal_laptime,
+        })
+        .collect();
+    Ok(Json(summaries))
+}
+
+// Dry and wet are separate boards, this picks which one to return
+#[derive(Deserialize)]
+pub(crate) struct ConditionsQuery {
+    #[serde(default)]
+    wet: bool,
+}
+
+pub(crate) async fn track(
+    extract::State(state): extract::State<State>,
+    Path(track): Path<String>,
+    Query(filter): Query<BoardFilter>,
+    Query(conditions): Query<ConditionsQuery>,
+) -> ApiResult<rootpage::TrackDisplayData> {
+    debug!(
+        "API: track {}, wet {}, filter {:?}",
+        track, conditions.wet, filter
+    );
+    let display_data = rootpage::get_display_data(state, filter).await?;
+    display_data
+        .into_iter()
+        .find(|track_data| track_data.id == track && track_data.wet == conditions.wet)
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+pub(crate) async fn track_details(
+    extract::State(state): extract::State<State>,
+    Path(track_id): Path<String>,
+    Query(filter): Query<BoardFilter>,
+) -> ApiResult<track::DisplayData> {
+    debug!("API: track details {}, filter {:?}", track_id, filter);
+    track::get_display_data(state, track_id, filter)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+pub(crate) async fn driver(
+    extract::State(state): extract::State<State>,
+    Path(steam_id): Path<i64>,
+) -> ApiResult<driver::DisplayData> {
+    debug!("API: driver {}", steam_id);
+    driver::get_display_data(state, steam_id)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
+
+pub(crate) async fn driver_track(
+    extract::State(state): extract::State<State>,
+    Path((steam_id, track)): Path<(i64, String)>,
+) -> ApiResult<driver::TrackLines> {
+    debug!("API: driver {} track {}", steam_id, track);
+    driver::get_display_data(state, steam_id)
+        .await?
+        .and_then(|display_data| {
+            display_data
+                .lines_per_track
+                .into_iter()
+                .find(|track_lines| track_lines.id == track)
+        })
+        .map(Json)
+        .ok_or(ApiError::NotFound)
+}
@@ -0,0 +1,190 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{extract, response::IntoResponse};
+use itertools::Itertools;
+use log::debug;
+use serde::Serialize;
+use sqlx::SqliteConnection;
+use std::time::Duration;
+
+use super::{
+    car_group_to_display_name, car_model_to_display_name, driver_display_name, nationality_to_flag,
+    track_id_to_display_name, BoardFilter, DurationWithClass, State,
+};
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayLine {
+    model: i64,
+    car: String,
+    class: String,
+    steam_id: i64,
+    name: String,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    laptime: DurationWithClass,
+    gap: Option<DurationWithClass>,
+    timestamp: i64,
+    drivers: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct TrackCarsData {
+    id: String,
+    name: String,
+    wet: bool,
+    display_lines: Vec<DisplayLine>,
+}
+
+pub(super) type DisplayData = Vec<TrackCarsData>;
+
+#[derive(Template)]
+#[template(path = "cars.html")]
+struct CarsTemplate {
+    display_data: DisplayData,
+}
+
+struct FastestLapPerCarQueryRow {
+    track: String,
+    wet: bool,
+    model: i64,
+    car_group: String,
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nationality: Option<i64>,
+    laptime_ms: i64,
+    timestamp: i64,
+    drivers: i64,
+}
+
+pub(crate) async fn handler(extract::State(state): extract::State<State>) -> impl IntoResponse {
+    debug!("cars page");
+    let display_data = get_display_data(state).await.unwrap();
+    CarsTemplate { display_data }
+}
+
+pub(super) async fn get_display_data(state: State) -> Result<DisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+
+    let filter = BoardFilter::default().with_config();
+
+    let fastest_laps_data = get_fastest_lap_per_car_data(&mut conn, &filter).await;
+
+    let mut display_data = fastest_laps_data
+        .into_iter()
+        .group_by(|row| (row.track.clone(), row.wet))
+        .into_iter()
+        .map(|((track, wet), rows)| {
+            let mut fastest_laptime = None;
+            let display_lines = rows
+                .map(|row| {
+                    let laptime = Duration::from_millis(row.laptime_ms.try_into().unwrap());
+                    let gap = fastest_laptime.map(|fastest_lap| laptime - fastest_lap);
+                    let mut laptime = DurationWithClass::new(laptime);
+                    if fastest_laptime.is_none() {
+                        laptime.class = "purple";
+                    }
+                    fastest_laptime = fastest_laptime.or(Some(laptime.duration));
+                    let (flag_code, flag_name) = nationality_to_flag(row.nationality);
+                    DisplayLine {
+                        model: row.model,
+                        car: car_model_to_display_name(row.model),
+                        class: car_group_to_display_name(&row.car_group),
+                        steam_id: row.steam_id,
+                        name: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+                        flag_code,
+                        flag_name,
+                        laptime,
+                        gap: gap.map(DurationWithClass::new),
+                        timestamp: row.timestamp,
+                        drivers: row.drivers,
+                    }
+                })
+                .collect::<Vec<_>>();
+            TrackCarsData {
+                name: track_id_to_display_name(&track),
+                id: track,
+                wet,
+                display_lines,
+            }
+        })
+        .collect::<DisplayData>();
+    display_data.sort_unstable_by_key(|track_data| {
+        (
+            -(track_data
+                .display_lines
+                .iter()
+                .map(|line| line.timestamp)
+                .max()
+                .unwrap_or(0)),
+            track_data.wet,
+        )
+    });
+    Ok(display_data)
+}
+
+async fn get_fastest_lap_per_car_data(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> Vec<FastestLapPerCarQueryRow> {
+    // Fastest lap for each car model on each track, and who drove it
+    sqlx::query_as!(
+        FastestLapPerCarQueryRow,
+        r#"
+        SELECT s.track,
+            s.wet AS "wet: bool",
+            c.model,
+            c.car_group,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            l.time_ms as laptime_ms,
+            s.timestamp,
+            (SELECT COUNT(DISTINCT COALESCE(da.merged_into, da.steam_id))
+             FROM laps dl
+             INNER JOIN sessions ds ON dl.session_id = ds.id
+             INNER JOIN cars dc ON dl.car_id = dc.id
+             INNER JOIN drivers da ON dl.steam_id = da.steam_id
+             WHERE ds.track = s.track AND ds.wet = s.wet AND dc.model = c.model
+             AND dl.valid = 1 AND dl.excluded = 0
+             AND (?2 IS NULL OR ds.type IN (SELECT value FROM json_each(?2)))
+            ) AS "drivers!: i64"
+        FROM sessions s
+        INNER JOIN laps l ON s.id = l.session_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        -- Same trick as the main leaderboard, but per car model instead of per driver.
+        WHERE l.id = (SELECT sl.id
+                      FROM laps sl
+                      INNER JOIN sessions ss ON sl.session_id = ss.id
+                      INNER JOIN cars sc ON sl.car_id = sc.id
+                      INNER JOIN drivers sa ON sl.steam_id = sa.steam_id
+                      INNER JOIN drivers sm ON COALESCE(sa.merged_into, sa.steam_id) = sm.steam_id
+                      WHERE ss.track = s.track AND ss.wet = s.wet
+                      AND sc.model = c.model AND sl.valid = 1 AND sl.excluded = 0
+                      AND sm.status = 'active'
+                      AND (?1 = 0 OR sl.penalty_served = 0)
+                      AND (?2 IS NULL OR ss.type IN (SELECT value FROM json_each(?2)))
+                      ORDER BY sl.time_ms, ss.timestamp
+                      LIMIT 1)
+        AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        AND (?1 = 0 OR l.penalty_served = 0)
+        AND (?2 IS NULL OR s.type IN (SELECT value FROM json_each(?2)))
+        AND (?3 IS NULL OR s.track IN (SELECT value FROM json_each(?3)))
+        AND (?4 IS NULL OR c.car_group IN (SELECT value FROM json_each(?4)))
+        ORDER BY s.track, s.wet, l.time_ms;
+    "#,
+        filter.exclude_penalty_laps,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
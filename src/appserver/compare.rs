@@ -0,0 +1,354 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{
+    extract,
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, NoneAsEmptyString};
+use sqlx::SqliteConnection;
+use std::{cmp::Ordering, time::Duration};
+
+use super::{
+    car_model_to_display_name, driver_display_name, format_duration, nationality_to_flag,
+    session_type_to_display_name, track_id_to_display_name, BoardFilter, DurationWithClass, State,
+};
+use crate::main_account;
+
+// Either two lap IDs, or a track and two drivers, in which case their best
+// laps on that track are compared. Empty strings come from the form.
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct CompareQuery {
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    a: Option<i64>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    b: Option<i64>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    track: Option<String>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    driver_a: Option<i64>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default)]
+    driver_b: Option<i64>,
+    #[serde(default)]
+    wet: bool,
+}
+
+// A signed difference between two times, negative means lap A was faster
+#[derive(Clone, Serialize)]
+pub(super) struct Delta {
+    ms: i64,
+    formatted: String,
+}
+
+impl Delta {
+    fn new(a: Duration, b: Duration) -> Self {
+        let ms = i64::try_from(a.as_millis()).unwrap() - i64::try_from(b.as_millis()).unwrap();
+        let sign = if ms < 0 { "-" } else { "+" };
+        Self {
+            ms,
+            formatted: format!(
+                "{sign}{}",
+                format_duration(Duration::from_millis(ms.unsigned_abs()))
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct LapInfo {
+    lap_id: i64,
+    session_id: i64,
+    steam_id: i64,
+    name: String,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    car: String,
+    laptime: DurationWithClass,
+    valid: bool,
+    session_type: &'static str,
+    timestamp: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SectorLine {
+    sector: i64,
+    a: DurationWithClass,
+    b: DurationWithClass,
+    delta: Delta,
+    cumulative: Delta,
+    gained_by: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayData {
+    track: String,
+    track_name: String,
+    wet: bool,
+    a: LapInfo,
+    b: LapInfo,
+    sectors: Vec<SectorLine>,
+    delta: Delta,
+}
+
+#[derive(Template)]
+#[template(path = "compare.html")]
+struct CompareTemplate {
+    display_data: Option<DisplayData>,
+    query: CompareQuery,
+    tracks: Vec<(String, String)>,
+    drivers: Vec<(i64, String)>,
+}
+
+struct LapQueryRow {
+    lap_id: i64,
+    session_id: i64,
+    track: String,
+    wet: bool,
+    session_type: String,
+    timestamp: i64,
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nationality: Option<i64>,
+    model: i64,
+    laptime_ms: i64,
+    valid: bool,
+    sector: i64,
+    sector_time_ms: i64,
+}
+
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<CompareQuery>,
+) -> Response {
+    debug!("compare page, query {:?}", query);
+    let mut conn = state.0.pool.acquire().await.unwrap();
+    let tracks = sqlx::query!("SELECT DISTINCT track FROM sessions ORDER BY track;")
+        .fetch_all(&mut *conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| {
+            let track_name = track_id_to_display_name(&row.track);
+            (row.track, track_name)
+        })
+        .collect();
+    let drivers = sqlx::query!(
+        "SELECT steam_id, first_name, last_name, short_name
+        FROM drivers
+        WHERE merged_into IS NULL AND status = 'active'
+        ORDER BY first_name, last_name;"
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| {
+        let name = driver_display_name(&row.first_name, &row.last_name, &row.short_name);
+        (row.steam_id, name)
+    })
+    .collect();
+    drop(conn);
+
+    let nothing_selected = query.a.is_none() && query.b.is_none() && query.track.is_none();
+    let display_data = get_display_data(state, query.clone()).await.unwrap();
+    if display_data.is_none() && !nothing_selected {
+        return (StatusCode::NOT_FOUND, "404 Not Found").into_response();
+    }
+    CompareTemplate {
+        display_data,
+        query,
+        tracks,
+        drivers,
+    }
+    .into_response()
+}
+
+pub(super) async fn get_display_data(
+    state: State,
+    query: CompareQuery,
+) -> Result<Option<DisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+
+    let (lap_a, lap_b) = match (
+        query.a,
+        query.b,
+        query.track,
+        query.driver_a,
+        query.driver_b,
+    ) {
+        (Some(a), Some(b), _, _, _) => (a, b),
+        (_, _, Some(track), Some(driver_a), Some(driver_b)) => {
+            let filter = BoardFilter::default().with_config();
+            let driver_a = main_account(&mut conn, driver_a).await?;
+            let driver_b = main_account(&mut conn, driver_b).await?;
+            let best_a = get_best_lap_id(&mut conn, &track, query.wet, driver_a, &filter).await;
+            let best_b = get_best_lap_id(&mut conn, &track, query.wet, driver_b, &filter).await;
+            let (Some(a), Some(b)) = (best_a, best_b) else {
+                return Ok(None);
+            };
+            (a, b)
+        }
+        _ => return Ok(None),
+    };
+
+    let rows_a = get_lap_data(&mut conn, lap_a).await;
+    let rows_b = get_lap_data(&mut conn, lap_b).await;
+    let (Some(first_a), Some(first_b)) = (rows_a.first(), rows_b.first()) else {
+        return Ok(None);
+    };
+    // Sectors on different tracks have nothing to do with each other
+    if first_a.track != first_b.track {
+        return Ok(None);
+    }
+    let mut a = lap_info(first_a);
+    let mut b = lap_info(first_b);
+    let track = first_a.track.clone();
+    let wet = first_a.wet;
+
+    let mut cumulative_a = Duration::ZERO;
+    let mut cumulative_b = Duration::ZERO;
+    let sectors = rows_a
+        .iter()
+        .zip(&rows_b)
+        .map(|(row_a, row_b)| {
+            let mut sector_a =
+                DurationWithClass::new(Duration::from_millis(row_a.sector_time_ms.try_into()?));
+            let mut sector_b =
+                DurationWithClass::new(Duration::from_millis(row_b.sector_time_ms.try_into()?));
+            cumulative_a += sector_a.duration;
+            cumulative_b += sector_b.duration;
+            let gained_by = match sector_a.duration.cmp(&sector_b.duration) {
+                Ordering::Less => {
+                    sector_a.class = "green";
+                    Some(a.name.clone())
+                }
+                Ordering::Greater => {
+                    sector_b.class = "green";
+                    Some(b.name.clone())
+                }
+                Ordering::Equal => None,
+            };
+            Ok(SectorLine {
+                sector: row_a.sector,
+                delta: Delta::new(sector_a.duration, sector_b.duration),
+                cumulative: Delta::new(cumulative_a, cumulative_b),
+                a: sector_a,
+                b: sector_b,
+                gained_by,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let delta = Delta::new(a.laptime.duration, b.laptime.duration);
+    match delta.ms {
+        ..=-1 => a.laptime.class = "green",
+        1.. => b.laptime.class = "green",
+        0 => {}
+    }
+
+    Ok(Some(DisplayData {
+        track_name: track_id_to_display_name(&track),
+        track,
+        wet,
+        a,
+        b,
+        sectors,
+        delta,
+    }))
+}
+
+fn lap_info(row: &LapQueryRow) -> LapInfo {
+    let (flag_code, flag_name) = nationality_to_flag(row.nationality);
+    LapInfo {
+        lap_id: row.lap_id,
+        session_id: row.session_id,
+        steam_id: row.steam_id,
+        name: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+        flag_code,
+        flag_name,
+        car: car_model_to_display_name(row.model),
+        laptime: DurationWithClass::new(Duration::from_millis(row.laptime_ms.try_into().unwrap())),
+        valid: row.valid,
+        session_type: session_type_to_display_name(&row.session_type),
+        timestamp: row.timestamp,
+    }
+}
+
+async fn get_best_lap_id(
+    conn: &mut SqliteConnection,
+    track: &str,
+    wet: bool,
+    steam_id: i64,
+    filter: &BoardFilter,
+) -> Option<i64> {
+    sqlx::query!(
+        r#"
+        SELECT l.id
+        FROM laps l
+        INNER JOIN sessions s ON l.session_id = s.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        WHERE s.track = ?1 AND s.wet = ?2 AND COALESCE(a.merged_into, a.steam_id) = ?3
+        AND l.valid = 1 AND l.excluded = 0
+        AND (?4 = 0 OR l.penalty_served = 0)
+        AND (?5 IS NULL OR s.type IN (SELECT value FROM json_each(?5)))
+        ORDER BY l.time_ms, s.timestamp
+        LIMIT 1;
+        "#,
+        track,
+        wet,
+        steam_id,
+        filter.exclude_penalty_laps,
+        filter.session_types
+    )
+    .fetch_optional(conn)
+    .await
+    .unwrap()
+    .map(|row| row.id)
+}
+
+async fn get_lap_data(conn: &mut SqliteConnection, lap_id: i64) -> Vec<LapQueryRow> {
+    sqlx::query_as!(
+        LapQueryRow,
+        r#"
+        SELECT l.id AS lap_id,
+            s.id AS session_id,
+            s.track,
+            s.wet AS "wet: bool",
+            s.type AS session_type,
+            s.timestamp,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            c.model,
+            l.time_ms AS laptime_ms,
+            l.valid AS "valid: bool",
+            sp.sector,
+            sp.time_ms AS sector_time_ms
+        FROM laps l
+        INNER JOIN sessions s ON l.session_id = s.id
+        INNER JOIN splits sp ON l.id = sp.lap_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE l.id = ?
+        ORDER BY sp.sector;
+        "#,
+        lap_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
@@ -0,0 +1,180 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{
+    extract,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use log::debug;
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use super::{car_group_to_display_name, rootpage, track_id_to_display_name, BoardFilter, State};
+
+#[derive(Clone, Serialize)]
+pub(super) struct Competition {
+    id: i64,
+    name: String,
+    track: String,
+    track_name: String,
+    class: Option<String>,
+    class_name: Option<String>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+impl Competition {
+    fn from_row(row: CompetitionQueryRow) -> Self {
+        Self {
+            id: row.id,
+            name: row.name,
+            track_name: track_id_to_display_name(&row.track),
+            track: row.track,
+            class_name: row.car_group.as_deref().map(car_group_to_display_name),
+            class: row.car_group,
+            start_timestamp: row.start_timestamp,
+            end_timestamp: row.end_timestamp,
+        }
+    }
+
+    fn board_filter(&self) -> BoardFilter {
+        BoardFilter {
+            class: self.class.clone(),
+            track: Some(self.track.clone()),
+            from: Some(self.start_timestamp),
+            until: Some(self.end_timestamp),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct IndexDisplayData {
+    current: Vec<Competition>,
+    upcoming: Vec<Competition>,
+    past: Vec<Competition>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayData {
+    competition: Competition,
+    boards: rootpage::DisplayData,
+}
+
+#[derive(Template)]
+#[template(path = "competitions.html")]
+struct IndexTemplate {
+    display_data: IndexDisplayData,
+}
+
+#[derive(Template)]
+#[template(path = "competition.html")]
+struct CompetitionTemplate {
+    display_data: DisplayData,
+}
+
+struct CompetitionQueryRow {
+    id: i64,
+    name: String,
+    track: String,
+    car_group: Option<String>,
+    start_timestamp: i64,
+    end_timestamp: i64,
+}
+
+pub(crate) async fn index_handler(
+    extract::State(state): extract::State<State>,
+) -> impl IntoResponse {
+    debug!("competitions page");
+    let display_data = get_index_display_data(state).await.unwrap();
+    IndexTemplate { display_data }
+}
+
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Path(competition_id): Path<i64>,
+) -> Response {
+    debug!("competition page for {}", competition_id);
+    match get_display_data(state, competition_id).await.unwrap() {
+        Some(display_data) => CompetitionTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+pub(super) async fn get_index_display_data(state: State) -> Result<IndexDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+    let now = Utc::now().timestamp();
+    let mut display_data = IndexDisplayData {
+        current: Vec::new(),
+        upcoming: Vec::new(),
+        past: Vec::new(),
+    };
+    for competition in get_competitions(&mut conn)
+        .await
+        .into_iter()
+        .map(Competition::from_row)
+    {
+        if competition.end_timestamp <= now {
+            display_data.past.push(competition);
+        } else if competition.start_timestamp > now {
+            display_data.upcoming.push(competition);
+        } else {
+            display_data.current.push(competition);
+        }
+    }
+    // Most recent first for past competitions, the rest are soonest first
+    display_data.past.reverse();
+    Ok(display_data)
+}
+
+pub(super) async fn get_display_data(
+    state: State,
+    competition_id: i64,
+) -> Result<Option<DisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+    let Some(competition) = get_competition(&mut conn, competition_id)
+        .await
+        .map(Competition::from_row)
+    else {
+        return Ok(None);
+    };
+    let boards = rootpage::get_display_data(state, competition.board_filter()).await?;
+    Ok(Some(DisplayData {
+        competition,
+        boards,
+    }))
+}
+
+async fn get_competitions(conn: &mut SqliteConnection) -> Vec<CompetitionQueryRow> {
+    sqlx::query_as!(
+        CompetitionQueryRow,
+        r#"
+        SELECT id, name, track, car_group, start_timestamp, end_timestamp
+        FROM competitions
+        ORDER BY start_timestamp, end_timestamp, id;
+        "#
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_competition(
+    conn: &mut SqliteConnection,
+    competition_id: i64,
+) -> Option<CompetitionQueryRow> {
+    sqlx::query_as!(
+        CompetitionQueryRow,
+        r#"
+        SELECT id, name, track, car_group, start_timestamp, end_timestamp
+        FROM competitions
+        WHERE id = ?;
+        "#,
+        competition_id
+    )
+    .fetch_optional(conn)
+    .await
+    .unwrap()
+}
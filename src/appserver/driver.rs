@@ -1,24 +1,38 @@
 use anyhow::Result;
 use askama_axum::Template;
-use axum::{extract, extract::Path, response::IntoResponse};
+use axum::{
+    extract,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
 use itertools::{izip, EitherOrBoth, Itertools};
 use log::debug;
+use serde::Serialize;
 use sqlx::SqliteConnection;
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
 
 use super::{
-    DurationWithClass, State, CAR_MODEL_ID_TO_NAME, NATIONALITY_TO_COUNTRY, NATIONALITY_TO_ISO,
+    cache_generation, car_model_to_display_name, format_duration, rootpage::get_fastest_splits,
+    session_type_to_display_name, track_id_to_display_name, BoardFilter, DurationWithClass,
+    PageCache, State, NATIONALITY_TO_COUNTRY, NATIONALITY_TO_ISO,
 };
+use crate::{config, main_account};
 
-#[derive(Clone)]
-struct DisplayLine {
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayLine {
     laptime: DurationWithClass,
     splits: Vec<DurationWithClass>,
     car: String,
     ballast_kg: Option<i64>,
     session_type: String,
+    session_id: i64,
     timestamp: i64,
     valid: bool,
+    // Taken off the boards by a moderator, with the reason if they gave one
+    excluded: bool,
+    excluded_reason: Option<String>,
+    wet: bool,
 }
 
 impl DisplayLine {
@@ -29,10 +43,14 @@ impl DisplayLine {
         laptime: Duration,
         model: i64,
         ballast_kg: Option<i64>,
-        mut session_type: String,
+        session_type: String,
+        session_id: i64,
         timestamp: i64,
         splits: &[Duration],
         valid: i64,
+        excluded: bool,
+        excluded_reason: Option<String>,
+        wet: bool,
     ) -> Self {
         // Laptime
         let laptime = DurationWithClass::new(laptime);
@@ -41,10 +59,7 @@ impl DisplayLine {
         let splits = splits.iter().copied().map(DurationWithClass::new).collect();
 
         // Car
-        let car = (*CAR_MODEL_ID_TO_NAME
-            .get(&(model.try_into().unwrap()))
-            .unwrap_or(&"Unknown"))
-        .to_string();
+        let car = car_model_to_display_name(model);
 
         // Valid
         let valid = match valid {
@@ -54,15 +69,7 @@ impl DisplayLine {
         };
 
         // Session type
-        match session_type.chars().next() {
-            Some('P') => session_type.push_str("ractice"),
-            Some('Q') => session_type.push_str("ualifying"),
-            Some('R') => session_type.push_str("ace"),
-            _ => {
-                session_type.clear();
-                session_type.push_str("Unknown");
-            }
-        }
+        let session_type = session_type_to_display_name(&session_type).to_string();
 
         // Combine it all together
         Self {
@@ -71,21 +78,83 @@ impl DisplayLine {
             car,
             ballast_kg,
             session_type,
+            session_id,
             timestamp,
             valid,
+            excluded,
+            excluded_reason,
+            wet,
         }
     }
 }
 
-#[derive(Clone)]
-struct DisplayData {
+#[derive(Clone, Serialize)]
+pub(super) struct TrackLines {
+    pub(super) id: String,
+    pub(super) name: String,
+    pub(super) lines: Vec<DisplayLine>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct PenaltyLine {
+    session_id: i64,
+    track: String,
+    track_name: String,
+    session_type: &'static str,
+    timestamp: i64,
+    reason: String,
+    penalty: String,
+    penalty_value: i64,
+    violation_in_lap: i64,
+    post_race: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct ModerationLine {
+    timestamp: i64,
+    excluded: bool,
+    session_id: Option<i64>,
+    track: String,
+    track_name: String,
+    laptime: String,
+    reason: Option<String>,
+    moderator: String,
+}
+
+// Another Steam account of the driver, linked to this one
+#[derive(Clone, Serialize)]
+pub(super) struct AccountLine {
     steam_id: i64,
     name: String,
+}
+
+// A name the driver showed up with, and when. Names from before this was kept
+// track of don't have times.
+#[derive(Clone, Serialize)]
+pub(super) struct NameLine {
+    name: String,
+    source: &'static str,
+    first_seen: Option<i64>,
+    last_seen: Option<i64>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayData {
+    steam_id: i64,
+    name: String,
+    // 'active', 'hidden' or 'banned', hidden and banned drivers aren't on the
+    // boards
+    status: String,
+    status_reason: Option<String>,
+    accounts: Vec<AccountLine>,
     flag_code: &'static str,
     flag_name: &'static str,
     valid_laps: i64,
     total_laps: i64,
-    lines_per_track: Vec<(String, Vec<DisplayLine>)>,
+    pub(super) lines_per_track: Vec<TrackLines>,
+    penalties: Vec<PenaltyLine>,
+    moderation: Vec<ModerationLine>,
+    names: Vec<NameLine>,
 }
 
 #[derive(Template)]
@@ -100,13 +169,31 @@ struct DriverLapsQueryRow {
     model: i64,
     ballast_kg: Option<i64>,
     session_type: String,
+    session_id: i64,
     timestamp: i64,
     sector_time_ms: i64,
     valid: i64,
+    excluded: bool,
+    excluded_reason: Option<String>,
+    wet: bool,
+}
+
+struct PenaltyQueryRow {
+    session_id: i64,
+    track: String,
+    session_type: String,
+    timestamp: i64,
+    reason: String,
+    penalty: String,
+    penalty_value: i64,
+    violation_in_lap: i64,
+    post_race: bool,
 }
 
 struct DriverData {
     name: String,
+    status: String,
+    status_reason: Option<String>,
     nationality: Option<i64>,
     valid_laps: i64,
     total_laps: i64,
@@ -115,22 +202,93 @@ struct DriverData {
 pub(crate) async fn handler(
     extract::State(state): extract::State<State>,
     Path(steam_id): Path<i64>,
-) -> impl IntoResponse {
+) -> Response {
     debug!("Driver page for steam_id {}", steam_id);
-    let display_data = get_display_data(state, steam_id).await.unwrap();
-    RootTemplate { display_data }
+    match get_display_data(state, steam_id).await.unwrap() {
+        // Linked accounts all share the main account's page
+        Some(display_data) if display_data.steam_id != steam_id => {
+            Redirect::to(&display_data.steam_id.to_string()).into_response()
+        }
+        Some(display_data) => RootTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
 }
 
-async fn get_display_data(state: State, steam_id: i64) -> Result<DisplayData> {
+// Shared by all driver pages, so only the first one after new laps come in
+// has to go through all of them.
+#[derive(Clone)]
+struct OverallFastest {
+    laps: HashMap<(String, bool), Duration>,
+    splits: HashMap<(String, bool, i64), Vec<Duration>>,
+}
+
+static OVERALL_FASTEST: LazyLock<PageCache<(), OverallFastest>> =
+    LazyLock::new(|| PageCache::new(1));
+
+async fn get_overall_fastest(state: &State) -> Result<OverallFastest> {
+    if !config::get().cache.enabled {
+        return overall_fastest(state).await;
+    }
+    if let Some(overall_fastest) = OVERALL_FASTEST.get(&()).await {
+        return Ok(overall_fastest);
+    }
+    let generation = cache_generation();
+    let overall_fastest = overall_fastest(state).await?;
+    OVERALL_FASTEST
+        .set((), overall_fastest.clone(), generation)
+        .await;
+    Ok(overall_fastest)
+}
+
+async fn overall_fastest(state: &State) -> Result<OverallFastest> {
     let mut conn = state.0.pool.acquire().await?;
+    let filter = BoardFilter::default().with_config();
+    Ok(OverallFastest {
+        laps: get_overall_fastest_laps(&mut conn, &filter).await,
+        splits: get_fastest_splits(&mut conn, &filter).await,
+    })
+}
 
-    let driver_laps_data = get_driver_laps_data(&mut conn, steam_id).await;
+pub(super) async fn clear_cache() {
+    OVERALL_FASTEST.clear().await;
+    DISPLAY_DATA.clear().await;
+}
 
-    let overall_fastest_laps = get_overall_fastest_laps(&mut conn).await;
+// Only the most recently viewed drivers, and only ones that exist, so that
+// asking for made up Steam IDs doesn't grow it
+static DISPLAY_DATA: LazyLock<PageCache<i64, DisplayData>> = LazyLock::new(|| PageCache::new(500));
 
-    let best_splits_data = get_fastest_splits(&mut conn).await;
+pub(super) async fn get_display_data(state: State, steam_id: i64) -> Result<Option<DisplayData>> {
+    if !config::get().cache.enabled {
+        return display_data(state, steam_id).await;
+    }
+    if let Some(display_data) = DISPLAY_DATA.get(&steam_id).await {
+        return Ok(Some(display_data));
+    }
+    let generation = cache_generation();
+    let display_data = display_data(state, steam_id).await?;
+    if let Some(display_data) = &display_data {
+        DISPLAY_DATA
+            .set(steam_id, display_data.clone(), generation)
+            .await;
+    }
+    Ok(display_data)
+}
 
-    let driver_data = get_driver_data(&mut conn, steam_id).await;
+async fn display_data(state: State, steam_id: i64) -> Result<Option<DisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+    let steam_id = main_account(&mut conn, steam_id).await?;
+
+    let Some(driver_data) = get_driver_data(&mut conn, steam_id).await else {
+        return Ok(None);
+    };
+
+    let driver_laps_data = get_driver_laps_data(&mut conn, steam_id).await;
+
+    let OverallFastest {
+        laps: overall_fastest_laps,
+        splits: best_splits_data,
+    } = get_overall_fastest(&state).await?;
 
     let mut lines_per_track = driver_laps_data
         .into_iter()
@@ -147,13 +305,31 @@ async fn get_display_data(state: State, steam_id: i64) -> Result<DisplayData> {
                         row.model,
                         row.ballast_kg,
                         row.session_type.clone(),
+                        row.session_id,
                         row.timestamp,
                         row.valid,
+                        row.excluded,
+                        row.excluded_reason.clone(),
+                        row.wet,
                     )
                 })
                 .into_iter()
                 .map(
-                    |((laptime_ms, model, ballast_kg, session_type, timestamp, valid), rows)| {
+                    |(
+                        (
+                            laptime_ms,
+                            model,
+                            ballast_kg,
+                            session_type,
+                            session_id,
+                            timestamp,
+                            valid,
+                            excluded,
+                            excluded_reason,
+                            wet,
+                        ),
+                        rows,
+                    )| {
                         // Prepare sector times
                         let splits = rows
                             .into_iter()
@@ -169,38 +345,45 @@ async fn get_display_data(state: State, steam_id: i64) -> Result<DisplayData> {
                             model,
                             ballast_kg,
                             session_type,
+                            session_id,
                             timestamp,
                             &splits,
                             valid,
+                            excluded,
+                            excluded_reason,
+                            wet,
                         )
                     },
                 )
                 .collect();
-            let driver_fastest_laptime = display_lines
-                .iter()
-                .filter_map(|line| {
-                    if line.valid {
-                        Some(line.laptime.duration)
-                    } else {
-                        None
-                    }
-                })
-                .min();
-            let overall_fastest_laptime = overall_fastest_laps.get(&track).copied();
-            set_purple_and_green(
-                &track,
-                steam_id,
-                &mut display_lines,
-                driver_fastest_laptime,
-                overall_fastest_laptime,
-                &best_splits_data,
-            );
-            Ok((display_track, display_lines))
+            // Dry and wet laps are ranked separately, so they are colored separately too
+            for wet in [false, true] {
+                let overall_fastest_laptime =
+                    overall_fastest_laps.get(&(track.clone(), wet)).copied();
+                set_purple_and_green(
+                    &track,
+                    wet,
+                    steam_id,
+                    &mut display_lines,
+                    overall_fastest_laptime,
+                    &best_splits_data,
+                );
+            }
+            Ok(TrackLines {
+                id: track,
+                name: display_track,
+                lines: display_lines,
+            })
         })
         .collect::<Result<Vec<_>>>()?;
     // Sort by latest driven
-    lines_per_track.sort_unstable_by_key(|(_, lines)| {
-        -(lines.iter().map(|line| line.timestamp).max().unwrap_or(0))
+    lines_per_track.sort_unstable_by_key(|track_lines| {
+        -(track_lines
+            .lines
+            .iter()
+            .map(|line| line.timestamp)
+            .max()
+            .unwrap_or(0))
     });
     let flag_code = driver_data
         .nationality
@@ -212,29 +395,70 @@ async fn get_display_data(state: State, steam_id: i64) -> Result<DisplayData> {
         .and_then(|n| NATIONALITY_TO_COUNTRY.get(&n))
         .copied()
         .unwrap_or("Unknown");
-    Ok(DisplayData {
+    let penalties = get_penalties(&mut conn, steam_id)
+        .await
+        .into_iter()
+        .map(|row| PenaltyLine {
+            session_id: row.session_id,
+            track_name: track_id_to_display_name(&row.track),
+            track: row.track,
+            session_type: session_type_to_display_name(&row.session_type),
+            timestamp: row.timestamp,
+            reason: row.reason,
+            penalty: row.penalty,
+            penalty_value: row.penalty_value,
+            violation_in_lap: row.violation_in_lap,
+            post_race: row.post_race,
+        })
+        .collect();
+    let moderation = get_moderation(&mut conn, steam_id).await;
+    let accounts = get_accounts(&mut conn, steam_id).await;
+    let names = get_names(&mut conn, steam_id).await;
+    Ok(Some(DisplayData {
         steam_id,
         name: driver_data.name,
+        status: driver_data.status,
+        status_reason: driver_data.status_reason,
+        accounts,
         flag_code,
         flag_name,
         valid_laps: driver_data.valid_laps,
         total_laps: driver_data.total_laps,
         lines_per_track,
-    })
+        penalties,
+        moderation,
+        names,
+    }))
 }
 
 fn set_purple_and_green(
     track: &str,
+    wet: bool,
     steam_id: i64,
-    display_lines: &mut Vec<DisplayLine>,
-    driver_fastest_laptime: Option<Duration>,
+    display_lines: &mut [DisplayLine],
     overall_fastest_laptime: Option<Duration>,
-    best_splits_data: &HashMap<(String, i64), Vec<Duration>>,
+    best_splits_data: &HashMap<(String, bool, i64), Vec<Duration>>,
 ) {
-    let driver_fastest_splits = best_splits_data.get(&(track.to_string(), steam_id));
+    let driver_fastest_laptime = display_lines
+        .iter()
+        .filter_map(|line| {
+            if line.valid && !line.excluded && line.wet == wet {
+                Some(line.laptime.duration)
+            } else {
+                None
+            }
+        })
+        .min();
+    let driver_fastest_splits = best_splits_data.get(&(track.to_string(), wet, steam_id));
     let overall_fastest_splits = best_splits_data
         .iter()
-        .filter_map(|(key, splits)| if key.0 == track { Some(splits) } else { None })
+        .filter_map(|(key, splits)| {
+            if key.0 == track && key.1 == wet {
+                Some(splits)
+            } else {
+                None
+            }
+        })
         .fold(Vec::new(), |acc: Vec<Duration>, splits| {
             acc.into_iter()
                 .zip_longest(splits)
@@ -259,7 +483,7 @@ fn set_purple_and_green(
         return;
     };
 
-    for display_line in display_lines {
+    for display_line in display_lines.iter_mut().filter(|line| line.wet == wet) {
         if overall_fastest_laptime.is_some_and(|ofl| display_line.laptime.duration == ofl) {
             display_line.laptime.class = "purple";
         } else if driver_fastest_laptime.is_some_and(|dfl| display_line.laptime.duration == dfl) {
@@ -279,20 +503,6 @@ fn set_purple_and_green(
     }
 }
 
-fn track_id_to_display_name(track: &str) -> String {
-    track
-        .replace('_', " ")
-        .split_whitespace()
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
-                None => String::new(),
-            }
-        })
-        .join(" ")
-}
-
 async fn get_driver_laps_data(
     conn: &mut SqliteConnection,
     steam_id: i64,
@@ -305,14 +515,26 @@ async fn get_driver_laps_data(
             c.model,
             c.ballast_kg,
             s.type as session_type,
+            s.id AS session_id,
             s.timestamp,
             sp.time_ms AS sector_time_ms,
-            l.valid
+            l.valid,
+            l.excluded AS "excluded: bool",
+            (SELECT e.reason
+             FROM lap_exclusions e
+             WHERE e.session_timestamp = s.timestamp
+             AND e.server_name = s.server_name
+             AND e.steam_id = l.steam_id
+             AND e.lap_number = l.lap_number
+             AND l.excluded = 1
+            ) AS "excluded_reason?: String",
+            s.wet AS "wet: bool"
         FROM sessions s
         INNER JOIN laps l ON s.id = l.session_id
         INNER JOIN splits sp ON l.id = sp.lap_id
         INNER JOIN cars c ON l.car_id = c.id
-        WHERE l.steam_id = ? 
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        WHERE COALESCE(a.merged_into, a.steam_id) = ?
         ORDER BY s.track, s.timestamp, l.id, sp.sector;
     "#,
         steam_id
@@ -322,84 +544,176 @@ async fn get_driver_laps_data(
     .unwrap()
 }
 
-async fn get_fastest_splits(conn: &mut SqliteConnection) -> HashMap<(String, i64), Vec<Duration>> {
+async fn get_overall_fastest_laps(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> HashMap<(String, bool), Duration> {
     sqlx::query!(
         r#"
-        SELECT s.track as "track!",
-            l.steam_id as "steam_id!",
-            sp.sector,
-            MIN(sp.time_ms) AS "sector_time_ms: i64"
-        FROM splits sp
-        INNER JOIN laps l ON sp.lap_id = l.id
-        INNER JOIN sessions s ON l.session_id = s.id
-        WHERE l.valid = 1
-        GROUP BY s.track, l.steam_id, sp.sector
-        ORDER BY s.track, l.steam_id, sp.sector;
-    "#
+        SELECT track AS "track!",
+            wet AS "wet!: bool",
+            MIN(time_ms) AS "laptime_ms!: i64"
+        FROM personal_best_laps
+        WHERE exclude_penalty_laps = ?1
+        AND (?2 IS NULL OR session_type IN (SELECT value FROM json_each(?2)))
+        AND (?3 IS NULL OR track IN (SELECT value FROM json_each(?3)))
+        AND (?4 IS NULL OR car_group IN (SELECT value FROM json_each(?4)))
+        GROUP BY track, wet;
+        "#,
+        filter.exclude_penalty_laps,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
     )
     .fetch_all(conn)
     .await
     .unwrap()
     .into_iter()
-    .group_by(|row| (row.track.clone(), row.steam_id))
-    .into_iter()
-    .map(|((track, steam_id), rows)| {
-        let best_sectors = rows
-            .into_iter()
-            .map(|row| Duration::from_millis(row.sector_time_ms.try_into().unwrap()))
-            .collect::<Vec<_>>();
-        ((track, steam_id), best_sectors)
+    .map(|row| {
+        let track = row.track;
+        let wet = row.wet;
+        let laptime = Duration::from_millis(row.laptime_ms.try_into().unwrap());
+        ((track, wet), laptime)
     })
     .collect::<HashMap<_, _>>()
 }
 
-async fn get_overall_fastest_laps(conn: &mut SqliteConnection) -> HashMap<String, Duration> {
-    sqlx::query!(
+async fn get_penalties(conn: &mut SqliteConnection, steam_id: i64) -> Vec<PenaltyQueryRow> {
+    sqlx::query_as!(
+        PenaltyQueryRow,
         r#"
-        SELECT s.track as "track!",
-            MIN(l.time_ms) AS "laptime_ms: i64"
-        FROM sessions s
-        INNER JOIN laps l ON s.id = l.session_id
-        WHERE l.valid = 1
-        GROUP BY s.track;
-        "#
+        SELECT s.id AS session_id,
+            s.track,
+            s.type AS session_type,
+            s.timestamp,
+            pe.reason,
+            pe.penalty,
+            pe.penalty_value,
+            pe.violation_in_lap,
+            pe.post_race AS "post_race: bool"
+        FROM penalties pe
+        INNER JOIN sessions s ON pe.session_id = s.id
+        INNER JOIN drivers a ON pe.steam_id = a.steam_id
+        WHERE COALESCE(a.merged_into, a.steam_id) = ?
+        ORDER BY s.timestamp DESC, pe.id;
+        "#,
+        steam_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+// Laps of the driver that moderators excluded or restored, newest first
+async fn get_moderation(conn: &mut SqliteConnection, steam_id: i64) -> Vec<ModerationLine> {
+    sqlx::query!(
+        r#"SELECT m.timestamp,
+            m.action,
+            (SELECT s.id
+             FROM sessions s
+             WHERE s.timestamp = m.session_timestamp AND s.server_name = m.server_name
+            ) AS "session_id?: i64",
+            m.track,
+            m.time_ms,
+            m.reason,
+            m.moderator
+        FROM lap_moderation m
+        INNER JOIN drivers a ON m.steam_id = a.steam_id
+        WHERE COALESCE(a.merged_into, a.steam_id) = ?
+        ORDER BY m.id DESC;"#,
+        steam_id
     )
     .fetch_all(conn)
     .await
     .unwrap()
     .into_iter()
-    .map(|row| {
-        let track = row.track;
-        let laptime = Duration::from_millis(row.laptime_ms.try_into().unwrap());
-        (track, laptime)
+    .map(|row| ModerationLine {
+        timestamp: row.timestamp,
+        excluded: row.action == "exclude",
+        session_id: row.session_id,
+        track_name: track_id_to_display_name(&row.track),
+        track: row.track,
+        laptime: format_duration(Duration::from_millis(row.time_ms.try_into().unwrap())),
+        reason: row.reason,
+        moderator: row.moderator,
     })
-    .collect::<HashMap<_, _>>()
+    .collect()
 }
 
-async fn get_driver_data(conn: &mut SqliteConnection, steam_id: i64) -> DriverData {
+async fn get_driver_data(conn: &mut SqliteConnection, steam_id: i64) -> Option<DriverData> {
     let row = sqlx::query!(
         r#"
         SELECT d.first_name,
             d.last_name, 
             d.short_name,
+            d.status,
+            d.status_reason,
             d.nationality,
-            COUNT(1) FILTER (WHERE l.valid = 1) AS "valid_laps: i64",
+            COUNT(1) FILTER (WHERE l.valid = 1 AND l.excluded = 0) AS "valid_laps: i64",
             COUNT(1) AS "total_laps: i64"
         FROM drivers d
-        INNER JOIN laps l ON d.steam_id = l.steam_id
+        INNER JOIN drivers a ON COALESCE(a.merged_into, a.steam_id) = d.steam_id
+        INNER JOIN laps l ON a.steam_id = l.steam_id
         WHERE d.steam_id = ?
-        GROUP BY d.first_name, d.last_name, d.short_name, d.nationality;
+        GROUP BY d.steam_id;
         "#,
         steam_id
     )
-    .fetch_one(conn)
+    .fetch_optional(conn)
     .await
-    .unwrap();
+    .unwrap()?;
     let name = format!("{} {} ({})", row.first_name, row.last_name, row.short_name);
-    DriverData {
+    Some(DriverData {
         name,
+        status: row.status,
+        status_reason: row.status_reason,
         nationality: row.nationality,
         valid_laps: row.valid_laps,
         total_laps: row.total_laps,
-    }
+    })
+}
+
+async fn get_accounts(conn: &mut SqliteConnection, steam_id: i64) -> Vec<AccountLine> {
+    sqlx::query!(
+        "SELECT steam_id, first_name, last_name, short_name
+        FROM drivers
+        WHERE merged_into = ?
+        ORDER BY steam_id;",
+        steam_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| AccountLine {
+        steam_id: row.steam_id,
+        name: format!("{} {} ({})", row.first_name, row.last_name, row.short_name),
+    })
+    .collect()
+}
+
+// Most recently seen first, of all of the driver's accounts
+async fn get_names(conn: &mut SqliteConnection, steam_id: i64) -> Vec<NameLine> {
+    sqlx::query!(
+        "SELECT n.first_name, n.last_name, n.short_name, n.source, n.first_seen, n.last_seen
+        FROM driver_names n
+        INNER JOIN drivers a ON n.steam_id = a.steam_id
+        WHERE COALESCE(a.merged_into, a.steam_id) = ?
+        ORDER BY n.last_seen DESC, n.first_seen DESC;",
+        steam_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| NameLine {
+        name: format!("{} {} ({})", row.first_name, row.last_name, row.short_name),
+        source: match row.source.as_str() {
+            "entrylist" => "Entry list",
+            _ => "Results",
+        },
+        first_seen: Some(row.first_seen).filter(|seen| *seen > 0),
+        last_seen: Some(row.last_seen).filter(|seen| *seen > 0),
+    })
+    .collect()
 }
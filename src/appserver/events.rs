@@ -0,0 +1,33 @@
+use axum::{
+    extract,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use log::{debug, warn};
+use std::convert::Infallible;
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+
+use super::State;
+
+// Server-Sent Events for everything that happens when a results file comes in
+// or an admin changes something, so pages and overlays can update without
+// polling.
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    debug!("SSE client connected");
+    let stream =
+        BroadcastStream::new(state.0.events.subscribe()).filter_map(|result| match result {
+            Ok(event) => Some(Ok(Event::default()
+                .event(event.name())
+                .json_data(&event)
+                .unwrap())),
+            Err(BroadcastStreamRecvError::Lagged(count)) => {
+                warn!("SSE client fell behind, skipped {} events", count);
+                None
+            }
+        });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -1,19 +1,48 @@
 use anyhow::{anyhow, Context};
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
+use axum::{
+    body::Bytes,
+    extract::{self, DefaultBodyLimit},
+    http::{header, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use cached::{Cached, SizedCache};
 use include_dir::{include_dir, Dir};
+use itertools::Itertools;
 use phf::{phf_map, Map};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use sqlx::SqlitePool;
 use std::{
-    env,
     fmt::{self, Display, Formatter},
-    sync::Arc,
+    fs,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::Duration,
 };
-use tokio::net::TcpListener;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::error::RecvError, Mutex},
+};
 use tower_serve_static::ServeDir;
 
+use crate::{config, events::EventSender};
+
+mod admin;
+mod api;
+mod cars;
+mod compare;
+mod competition;
 mod driver;
+mod events;
 mod rootpage;
+mod session;
+mod track;
+mod upload;
 
 static NATIONALITY_TO_COUNTRY: Map<i64, &'static str> = phf_map! {
     0_i64 => "Other",
@@ -253,7 +282,118 @@ static CAR_MODEL_ID_TO_NAME: Map<u64, &'static str> = phf_map! {
     86_u64 => "Porsche 935",
 };
 
-fn format_duration(duration: Duration) -> String {
+fn car_model_to_display_name(model: i64) -> String {
+    (*CAR_MODEL_ID_TO_NAME
+        .get(&(model.try_into().unwrap()))
+        .unwrap_or(&"Unknown"))
+    .to_string()
+}
+
+fn driver_display_name(first_name: &str, last_name: &str, short_name: &str) -> String {
+    format!("{first_name} {last_name} ({short_name})")
+}
+
+// Returns the flag code and country name for a driver's nationality
+fn nationality_to_flag(nationality: Option<i64>) -> (&'static str, &'static str) {
+    let flag_code = nationality
+        .and_then(|n| NATIONALITY_TO_ISO.get(&n))
+        .copied()
+        .unwrap_or("xx");
+    let flag_name = nationality
+        .and_then(|n| NATIONALITY_TO_COUNTRY.get(&n))
+        .copied()
+        .unwrap_or("Unknown");
+    (flag_code, flag_name)
+}
+
+fn session_type_to_display_name(session_type: &str) -> &'static str {
+    match session_type.chars().next() {
+        Some('P') => "Practice",
+        Some('Q') => "Qualifying",
+        Some('R') => "Race",
+        _ => "Unknown",
+    }
+}
+
+static CAR_GROUP_TO_NAME: Map<&'static str, &'static str> = phf_map! {
+    "GT3" => "GT3",
+    "GT4" => "GT4",
+    "GT2" => "GT2",
+    "CUP" => "Cup",
+    "ST" => "Super Trofeo",
+    "CHL" => "Challenge",
+    "TCX" => "TCX",
+};
+
+fn car_group_to_display_name(car_group: &str) -> String {
+    CAR_GROUP_TO_NAME
+        .get(car_group)
+        .map_or_else(|| car_group.to_string(), |name| (*name).to_string())
+}
+
+// Which subset of laps a leaderboard is built from. Comes straight from the
+// query string, so `?class=GT4` gives the GT4 boards. `None` means no
+// filtering on that field. `from` and `until` are session timestamps, `from`
+// is inclusive and `until` is exclusive.
+#[derive(Clone, Debug, Default, Deserialize, Hash, PartialEq, Eq)]
+struct BoardFilter {
+    class: Option<String>,
+    model: Option<i64>,
+    track: Option<String>,
+    from: Option<i64>,
+    until: Option<i64>,
+    // Matches both the server name from the results files and the label of
+    // the results directory
+    server: Option<String>,
+    // Not user facing filters, these come from the server configuration.
+    // The lists are JSON arrays so that SQLite's `json_each` can go through
+    // them, `None` means everything.
+    #[serde(skip)]
+    exclude_penalty_laps: bool,
+    #[serde(skip)]
+    session_types: Option<String>,
+    #[serde(skip)]
+    tracks: Option<String>,
+    #[serde(skip)]
+    classes: Option<String>,
+}
+
+impl BoardFilter {
+    // The personal best tables only know about track, conditions and car
+    // group, anything narrower has to go through all laps.
+    fn uses_personal_bests(&self) -> bool {
+        self.model.is_none() && self.from.is_none() && self.until.is_none() && self.server.is_none()
+    }
+
+    fn with_config(self) -> Self {
+        let boards = &config::get().boards;
+        let json_list =
+            |list: &[String]| (!list.is_empty()).then(|| serde_json::to_string(list).unwrap());
+        Self {
+            exclude_penalty_laps: boards.exclude_penalty_laps,
+            session_types: json_list(&boards.session_types),
+            tracks: json_list(&boards.tracks),
+            classes: json_list(&boards.classes),
+            ..self
+        }
+    }
+}
+
+fn track_id_to_display_name(track: &str) -> String {
+    track
+        .replace('_', " ")
+        .split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .join(" ")
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
     let seconds = duration.as_secs();
     let minutes = seconds / 60;
     let seconds = seconds % 60;
@@ -286,8 +426,26 @@ impl Display for DurationWithClass {
     }
 }
 
+// For the API we want both the raw milliseconds (for consumers that do their
+// own formatting) and the string as it is shown on the website.
+impl Serialize for DurationWithClass {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("DurationWithClass", 3)?;
+        state.serialize_field("ms", &u64::try_from(self.duration.as_millis()).unwrap())?;
+        state.serialize_field("formatted", &format_duration(self.duration))?;
+        state.serialize_field("class", self.class)?;
+        state.end()
+    }
+}
+
 struct StateInner {
     pool: SqlitePool,
+    events: EventSender,
+    // Content type and image, either from the configuration or built in
+    header_logo: (&'static str, Bytes),
 }
 
 #[derive(Clone)]
@@ -295,18 +453,143 @@ struct State(Arc<StateInner>);
 
 static STATIC_DIR: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/static");
 
-pub async fn run(pool: SqlitePool) -> Result<(), anyhow::Error> {
-    let state = State(Arc::new(StateInner { pool }));
+pub async fn run(pool: SqlitePool, events: EventSender) -> Result<(), anyhow::Error> {
+    let config = config::get();
+    let bind_address = &config.bind_address;
+
+    // Cached pages stay as they are until new laps come in
+    let mut receiver = events.subscribe();
+    tokio::spawn(async move {
+        while let Ok(_) | Err(RecvError::Lagged(_)) = receiver.recv().await {
+            clear_caches().await;
+        }
+    });
+    // Or until they're too old, if so configured
+    if config.cache.enabled && config.cache.max_age_secs > 0 {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.cache.max_age_secs));
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                clear_caches().await;
+            }
+        });
+    }
+
+    let logo = match &config.site.header_logo {
+        Some(path) => (
+            config::image_content_type(path).unwrap(),
+            fs::read(path)
+                .with_context(|| format!("Failed to read header logo {}", path.display()))?
+                .into(),
+        ),
+        None => (
+            "image/png",
+            Bytes::from_static(STATIC_DIR.get_file("header_logo.png").unwrap().contents()),
+        ),
+    };
 
-    let bind_address = env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1:3000".to_string());
+    let state = State(Arc::new(StateInner {
+        pool,
+        events,
+        header_logo: logo,
+    }));
 
     let app = Router::new()
         .route("/", get(rootpage::handler))
+        .route("/header_logo", get(header_logo))
         .route("/driver/:driver_id", get(driver::handler))
+        .route("/track/:track", get(track::handler))
+        .route("/cars", get(cars::handler))
+        .route("/competitions", get(competition::index_handler))
+        .route("/competition/:competition_id", get(competition::handler))
+        .route("/compare", get(compare::handler))
+        .route("/sessions", get(session::index_handler))
+        .route("/session/:session_id", get(session::handler))
+        .route("/api/v1/classes", get(api::classes))
+        .route("/api/v1/models", get(api::models))
+        .route("/api/v1/cars", get(api::fastest_per_car))
+        .route("/api/v1/compare", get(api::compare))
+        .route("/api/v1/competitions", get(api::competitions))
+        .route(
+            "/api/v1/competitions/:competition_id",
+            get(api::competition),
+        )
+        .route("/api/v1/servers", get(api::servers))
+        .route("/api/v1/sessions", get(api::sessions))
+        .route("/api/v1/sessions/:session_id", get(api::session))
+        .route("/api/v1/tracks", get(api::tracks))
+        .route("/api/v1/tracks/:track", get(api::track))
+        .route("/api/v1/tracks/:track/details", get(api::track_details))
+        .route("/api/v1/drivers/:driver_id", get(api::driver))
+        .route("/api/v1/events", get(events::handler))
+        .route(
+            "/api/v1/upload/:filename",
+            post(upload::handler).layer(DefaultBodyLimit::max(upload::MAX_UPLOAD_SIZE)),
+        )
+        .route(
+            "/api/v1/drivers/:driver_id/tracks/:track",
+            get(api::driver_track),
+        )
+        // Goes to the same place as `/admin`, that `nest` doesn't do
+        .route("/admin/", get(admin::index_handler))
+        .nest(
+            "/admin",
+            Router::new()
+                .route("/status", get(admin::status_handler))
+                .route(
+                    "/sessions",
+                    get(admin::sessions_handler).post(admin::restore_handler),
+                )
+                .route(
+                    "/sessions/:session_id",
+                    get(admin::session_handler).post(admin::session_action_handler),
+                )
+                .route("/drivers", get(admin::drivers_handler))
+                .route(
+                    "/drivers/:steam_id",
+                    get(admin::driver_handler).post(admin::driver_edit_handler),
+                )
+                .route("/drivers/:steam_id/link", post(admin::driver_link_handler))
+                .route(
+                    "/drivers/:steam_id/status",
+                    post(admin::driver_status_handler),
+                )
+                .route(
+                    "/drivers/:steam_id/unlink",
+                    post(admin::driver_unlink_handler),
+                )
+                .route(
+                    "/competitions",
+                    get(admin::competitions_handler).post(admin::competition_create_handler),
+                )
+                .route(
+                    "/competitions/:competition_id",
+                    get(admin::competition_handler).post(admin::competition_edit_handler),
+                )
+                .route(
+                    "/competitions/:competition_id/delete",
+                    post(admin::competition_delete_handler),
+                )
+                .route(
+                    "/quarantine",
+                    get(admin::quarantine_handler).post(admin::retry_handler),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    admin::require_admin,
+                ))
+                // Everything above needs a logged in admin, these don't
+                .route("/", get(admin::index_handler))
+                .route(
+                    "/login",
+                    get(admin::login_page_handler).post(admin::login_handler),
+                )
+                .route("/logout", post(admin::logout_handler)),
+        )
         .with_state(state.clone())
         .nest_service("/static", ServeDir::new(&STATIC_DIR))
         .fallback(handler_404);
-    let listener = TcpListener::bind(&bind_address)
+    let listener = TcpListener::bind(bind_address)
         .await
         .context(anyhow!("Failed to bind to {bind_address}"))?;
     axum::serve(listener, app)
@@ -315,6 +598,57 @@ pub async fn run(pool: SqlitePool) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// Doesn't give away how much of a password or token was right by how long it
+// takes
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Goes up every time the caches are cleared, so that a page that was read
+// from the database before that doesn't end up in the cache after it
+static CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// To be called before reading anything from the database, and passed to
+// `PageCache::set` along with what was read
+fn cache_generation() -> u64 {
+    CACHE_GENERATION.load(Ordering::SeqCst)
+}
+
+// Pages that are kept until new laps come in, only the most recently used ones
+struct PageCache<K, V>(Mutex<SizedCache<K, V>>);
+
+impl<K: Hash + Eq + Clone, V: Clone> PageCache<K, V> {
+    fn new(size: usize) -> Self {
+        Self(Mutex::new(SizedCache::with_size(size)))
+    }
+
+    async fn get(&self, key: &K) -> Option<V> {
+        self.0.lock().await.cache_get(key).cloned()
+    }
+
+    async fn set(&self, key: K, value: V, generation: u64) {
+        let mut cache = self.0.lock().await;
+        if generation == cache_generation() {
+            cache.cache_set(key, value);
+        }
+    }
+
+    async fn clear(&self) {
+        self.0.lock().await.cache_clear();
+    }
+}
+
+async fn clear_caches() {
+    CACHE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    rootpage::clear_cache().await;
+    driver::clear_cache().await;
+}
+
+async fn header_logo(extract::State(state): extract::State<State>) -> impl IntoResponse {
+    let (content_type, image) = &state.0.header_logo;
+    ([(header::CONTENT_TYPE, *content_type)], image.clone())
+}
+
 async fn handler_404() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "404 Not Found")
 }
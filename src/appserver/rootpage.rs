@@ -1,34 +1,41 @@
 use anyhow::Result;
 use askama_axum::Template;
-use axum::{extract, response::IntoResponse};
-use cached::proc_macro::once;
+use axum::{
+    extract::{self, Query},
+    response::IntoResponse,
+};
 use itertools::{izip, EitherOrBoth, Itertools};
 use log::debug;
+use serde::Serialize;
 use sqlx::SqliteConnection;
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
 
 use super::{
-    format_duration, DurationWithClass, State, CAR_MODEL_ID_TO_NAME, NATIONALITY_TO_COUNTRY,
-    NATIONALITY_TO_ISO,
+    cache_generation, car_group_to_display_name, car_model_to_display_name,
+    track_id_to_display_name, BoardFilter, DurationWithClass, PageCache, State,
+    NATIONALITY_TO_COUNTRY, NATIONALITY_TO_ISO,
 };
+use crate::config;
 
-#[derive(Clone)]
-struct DisplayLine {
-    steam_id: i64,
-    name: String,
-    flag_code: &'static str,
-    flag_name: &'static str,
-    laptime: DurationWithClass,
-    optimal_laptime: DurationWithClass,
-    gap: String,
-    interval: String,
-    splits: Vec<DurationWithClass>,
-    best_splits: Vec<DurationWithClass>,
-    car: String,
-    ballast_kg: Option<i64>,
-    timestamp: i64,
-    valid_laps: i64,
-    total_laps: i64,
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayLine {
+    pub(super) lap_id: i64,
+    pub(super) provisional: bool,
+    pub(super) steam_id: i64,
+    pub(super) name: String,
+    pub(super) flag_code: &'static str,
+    pub(super) flag_name: &'static str,
+    pub(super) laptime: DurationWithClass,
+    pub(super) optimal_laptime: DurationWithClass,
+    pub(super) gap: Option<DurationWithClass>,
+    pub(super) interval: Option<DurationWithClass>,
+    pub(super) splits: Vec<DurationWithClass>,
+    pub(super) best_splits: Vec<DurationWithClass>,
+    pub(super) car: String,
+    pub(super) ballast_kg: Option<i64>,
+    pub(super) timestamp: i64,
+    pub(super) valid_laps: i64,
+    pub(super) total_laps: i64,
 }
 
 impl DisplayLine {
@@ -36,6 +43,8 @@ impl DisplayLine {
     // place this is called from, so it's fine.
     #[allow(clippy::too_many_arguments)]
     fn new(
+        lap_id: i64,
+        provisional: bool,
         steam_id: i64,
         first_name: &str,
         last_name: &str,
@@ -57,8 +66,8 @@ impl DisplayLine {
         let name = format!("{first_name} {last_name} ({short_name})");
 
         // Gap and interval
-        let gap = gap.map_or_else(String::new, format_duration);
-        let interval = interval.map_or_else(String::new, format_duration);
+        let gap = gap.map(DurationWithClass::new);
+        let interval = interval.map(DurationWithClass::new);
 
         // (Optimal) laptime
         let laptime = DurationWithClass::new(laptime);
@@ -73,10 +82,7 @@ impl DisplayLine {
             .collect();
 
         // Car
-        let car = (*CAR_MODEL_ID_TO_NAME
-            .get(&(model.try_into().unwrap()))
-            .unwrap_or(&"Unknown"))
-        .to_string();
+        let car = car_model_to_display_name(model);
 
         // Flag & country name
         let natl = nationality;
@@ -91,6 +97,8 @@ impl DisplayLine {
 
         // Combine it all together
         Self {
+            lap_id,
+            provisional,
             steam_id,
             name,
             flag_code,
@@ -110,23 +118,34 @@ impl DisplayLine {
     }
 }
 
-#[derive(Clone)]
-struct TrackDisplayData {
-    name: String,
-    overall_optimal_laptime: DurationWithClass,
-    display_lines: Vec<DisplayLine>,
+#[derive(Clone, Serialize)]
+pub(super) struct TrackDisplayData {
+    pub(super) id: String,
+    pub(super) name: String,
+    pub(super) wet: bool,
+    pub(super) overall_optimal_laptime: DurationWithClass,
+    pub(super) display_lines: Vec<DisplayLine>,
 }
 
-type DisplayData = Vec<TrackDisplayData>;
+pub(super) type DisplayData = Vec<TrackDisplayData>;
 
 #[derive(Template)]
 #[template(path = "root.html")]
 struct RootTemplate {
     display_data: DisplayData,
+    classes: Vec<(String, String)>,
+    selected_class: Option<String>,
+    models: Vec<(i64, String)>,
+    selected_model: Option<i64>,
+    servers: Vec<String>,
+    selected_server: Option<String>,
 }
 
 struct FastestLapQueryRow {
     track: String,
+    wet: bool,
+    lap_id: i64,
+    provisional: bool,
     steam_id: i64,
     first_name: String,
     last_name: String,
@@ -139,27 +158,131 @@ struct FastestLapQueryRow {
     sector_time_ms: i64,
 }
 
-pub(crate) async fn handler(extract::State(state): extract::State<State>) -> impl IntoResponse {
-    debug!("root page");
-    let display_data = get_display_data(state).await.unwrap();
-    RootTemplate { display_data }
+struct FastestSplitQueryRow {
+    track: String,
+    wet: bool,
+    steam_id: i64,
+    sector_time_ms: i64,
+}
+
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Query(filter): Query<BoardFilter>,
+) -> impl IntoResponse {
+    debug!("root page, filter {:?}", filter);
+    let classes = get_car_groups(&state)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|car_group| {
+            let display_name = car_group_to_display_name(&car_group);
+            (car_group, display_name)
+        })
+        .collect();
+    let models = get_car_models(&state, filter.class.as_deref())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|model| (model, car_model_to_display_name(model)))
+        .collect();
+    let servers = get_servers(&state).await.unwrap();
+    let selected_class = filter.class.clone();
+    let selected_model = filter.model;
+    let selected_server = filter.server.clone();
+    let display_data = get_display_data(state, filter).await.unwrap();
+    RootTemplate {
+        display_data,
+        classes,
+        selected_class,
+        models,
+        selected_model,
+        servers,
+        selected_server,
+    }
+}
+
+pub(super) async fn get_car_groups(state: &State) -> Result<Vec<String>> {
+    let mut conn = state.0.pool.acquire().await?;
+    Ok(
+        sqlx::query!("SELECT DISTINCT car_group FROM cars ORDER BY car_group;")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.car_group)
+            .collect(),
+    )
+}
+
+// Servers are known by their label if their results directory has one,
+// filtering works with either.
+pub(super) async fn get_servers(state: &State) -> Result<Vec<String>> {
+    let mut conn = state.0.pool.acquire().await?;
+    Ok(sqlx::query!(
+        r#"
+        SELECT DISTINCT COALESCE(server_label, server_name) AS "server!: String"
+        FROM sessions
+        ORDER BY 1;
+        "#
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| row.server)
+    .collect())
+}
+
+pub(super) async fn get_car_models(state: &State, car_group: Option<&str>) -> Result<Vec<i64>> {
+    let mut conn = state.0.pool.acquire().await?;
+    Ok(sqlx::query!(
+        "SELECT DISTINCT model FROM cars WHERE ?1 IS NULL OR car_group = ?1 ORDER BY model;",
+        car_group
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| row.model)
+    .collect())
+}
+
+pub(super) async fn clear_cache() {
+    DISPLAY_DATA.clear().await;
 }
 
-#[once(time = 60, result = true)]
-async fn get_display_data(state: State) -> Result<DisplayData> {
+// Every combination of query parameters is a filter of its own, so only the
+// most recently used ones are kept
+static DISPLAY_DATA: LazyLock<PageCache<BoardFilter, DisplayData>> =
+    LazyLock::new(|| PageCache::new(100));
+
+pub(super) async fn get_display_data(state: State, filter: BoardFilter) -> Result<DisplayData> {
+    if !config::get().cache.enabled {
+        return display_data(state, filter).await;
+    }
+    if let Some(display_data) = DISPLAY_DATA.get(&filter).await {
+        return Ok(display_data);
+    }
+    let generation = cache_generation();
+    let display_data = display_data(state, filter.clone()).await?;
+    DISPLAY_DATA
+        .set(filter, display_data.clone(), generation)
+        .await;
+    Ok(display_data)
+}
+
+async fn display_data(state: State, filter: BoardFilter) -> Result<DisplayData> {
     let mut conn = state.0.pool.acquire().await?;
+    let filter = filter.with_config();
 
-    let fastest_laps_data = get_fastest_laps_data(&mut conn).await;
+    let fastest_laps_data = get_fastest_laps_data(&mut conn, &filter).await;
 
-    let fastest_splits_data = get_fastest_splits(&mut conn).await;
+    let fastest_splits_data = get_fastest_splits(&mut conn, &filter).await;
 
-    let laps_data = get_lap_counts(&mut conn).await;
+    let laps_data = get_lap_counts(&mut conn, &filter).await;
 
     let mut display_data = fastest_laps_data
         .into_iter()
-        .group_by(|row| row.track.clone())
+        .group_by(|row| (row.track.clone(), row.wet))
         .into_iter()
-        .map(|(track, rows)| {
+        .map(|((track, wet), rows)| {
             // Replace underscore with space and capitalize every first letter of each word in the trackname
             let display_track = track_id_to_display_name(&track);
             let mut fastest_laptime = None;
@@ -167,7 +290,13 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
             let mut fastest_optimal_time = None;
             let overall_fastest_splits = fastest_splits_data
                 .iter()
-                .filter_map(|((t, _), splits)| if *t == track { Some(splits) } else { None })
+                .filter_map(|((t, w, _), splits)| {
+                    if *t == track && *w == wet {
+                        Some(splits)
+                    } else {
+                        None
+                    }
+                })
                 .fold(Vec::new(), |acc, splits| {
                     acc.into_iter()
                         .zip_longest(splits)
@@ -182,7 +311,7 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                             EitherOrBoth::Left(_) => {
                                 unreachable!("Accumulator should never have more values")
                             }
-                                    EitherOrBoth::Right(b) => *b,
+                            EitherOrBoth::Right(b) => *b,
                         })
                         .collect()
                 });
@@ -191,6 +320,8 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                 .into_iter()
                 .group_by(|row| {
                     (
+                        row.lap_id,
+                        row.provisional,
                         row.steam_id,
                         row.first_name.clone(),
                         row.last_name.clone(),
@@ -206,6 +337,8 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                 .map(
                     |(
                         (
+                            lap_id,
+                            provisional,
                             steam_id,
                             first_name,
                             last_name,
@@ -225,7 +358,9 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                                 Duration::from_millis(row.sector_time_ms.try_into().unwrap())
                             })
                             .collect::<Vec<_>>();
-                        let fastest_splits = fastest_splits_data.get(&(track.clone(), steam_id)).unwrap();
+                        let fastest_splits = fastest_splits_data
+                            .get(&(track.clone(), wet, steam_id))
+                            .unwrap();
 
                         let laptime = Duration::from_millis(laptime_ms.try_into().unwrap());
                         // For a single lap, the splits added up can deviate by
@@ -252,9 +387,11 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                         fastest_optimal_time = fastest_optimal_time.or(Some(optimal_laptime));
 
                         let (valid_laps, total_laps) =
-                            laps_data.get(&(track.clone(), steam_id)).unwrap();
+                            laps_data.get(&(track.clone(), wet, steam_id)).unwrap();
 
                         DisplayLine::new(
+                            lap_id,
+                            provisional,
                             steam_id,
                             &first_name,
                             &last_name,
@@ -283,19 +420,33 @@ async fn get_display_data(state: State) -> Result<DisplayData> {
                 &overall_fastest_splits,
             );
             Ok(TrackDisplayData {
+                id: track,
                 name: display_track,
+                wet,
                 overall_optimal_laptime,
                 display_lines,
             })
         })
         .collect::<Result<DisplayData>>()?;
-    display_data.sort_unstable_by_key(|track_data| {
-        -(track_data
+    // Sort by latest activity on the track, in either condition, so that the
+    // dry and wet boards for a track stay together.
+    let mut latest_per_track = HashMap::new();
+    for track_data in &display_data {
+        let latest = track_data
             .display_lines
             .iter()
             .map(|line| line.timestamp)
             .max()
-            .unwrap_or(0))
+            .unwrap_or(0);
+        let entry = latest_per_track.entry(track_data.id.clone()).or_insert(0);
+        *entry = latest.max(*entry);
+    }
+    display_data.sort_unstable_by_key(|track_data| {
+        (
+            -latest_per_track[&track_data.id],
+            track_data.id.clone(),
+            track_data.wet,
+        )
     });
     Ok(display_data)
 }
@@ -330,26 +481,21 @@ fn set_purple_and_green(
     }
 }
 
-fn track_id_to_display_name(track: &str) -> String {
-    track
-        .replace('_', " ")
-        .split_whitespace()
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
-                None => String::new(),
-            }
-        })
-        .join(" ")
-}
-
-async fn get_fastest_laps_data(conn: &mut SqliteConnection) -> Vec<FastestLapQueryRow> {
+async fn get_fastest_laps_data(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> Vec<FastestLapQueryRow> {
+    if filter.uses_personal_bests() {
+        return get_personal_best_laps_data(conn, filter).await;
+    }
     // Fastest laps for all drivers on all tracks
     sqlx::query_as!(
         FastestLapQueryRow,
         r#"
         SELECT s.track,
+            s.wet AS "wet: bool",
+            l.id AS lap_id,
+            l.provisional AS "provisional: bool",
             p.steam_id,
             p.first_name,
             p.last_name, 
@@ -364,68 +510,252 @@ async fn get_fastest_laps_data(conn: &mut SqliteConnection) -> Vec<FastestLapQue
         INNER JOIN laps l ON s.id = l.session_id
         INNER JOIN splits sp ON l.id = sp.lap_id
         INNER JOIN cars c ON l.car_id = c.id
-        INNER JOIN drivers p ON l.steam_id = p.steam_id
+        -- Laps of linked Steam accounts count as the main account's
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
         -- Subquery needed to find the fastest valid lap for each driver on each track.
         -- We need to use LIMIT, so subquery it is.
         WHERE l.id = (SELECT sl.id
                       FROM laps sl
                       INNER JOIN sessions ss ON sl.session_id = ss.id
-                      WHERE ss.track = s.track AND sl.steam_id = l.steam_id AND sl.valid = 1
+                      INNER JOIN cars sc ON sl.car_id = sc.id
+                      INNER JOIN drivers sa ON sl.steam_id = sa.steam_id
+                      WHERE ss.track = s.track AND ss.wet = s.wet
+                      AND COALESCE(sa.merged_into, sa.steam_id) = p.steam_id
+                      AND sl.valid = 1 AND sl.excluded = 0
+                      AND (?1 IS NULL OR sc.car_group = ?1)
+                      AND (?2 IS NULL OR sc.model = ?2)
+                      AND (?4 IS NULL OR ss.timestamp >= ?4)
+                      AND (?5 IS NULL OR ss.timestamp < ?5)
+                      AND (?6 = 0 OR sl.penalty_served = 0)
+                      AND (?7 IS NULL OR ss.server_name = ?7 OR ss.server_label = ?7)
+                      AND (?8 IS NULL OR ss.type IN (SELECT value FROM json_each(?8)))
+                      AND (?9 IS NULL OR ss.track IN (SELECT value FROM json_each(?9)))
+                      AND (?10 IS NULL OR sc.car_group IN (SELECT value FROM json_each(?10)))
                       ORDER BY sl.time_ms, ss.timestamp
                       LIMIT 1)
-        -- Valid lap is superflous here, but it's a good habit to include it
-        AND l.valid = 1
-        ORDER BY s.track, l.time_ms;
-    "#
+        -- Valid lap and car filters are superflous here, but it's a good habit to include them
+        AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        AND (?1 IS NULL OR c.car_group = ?1)
+        AND (?2 IS NULL OR c.model = ?2)
+        AND (?3 IS NULL OR s.track = ?3)
+        AND (?4 IS NULL OR s.timestamp >= ?4)
+        AND (?5 IS NULL OR s.timestamp < ?5)
+        AND (?6 = 0 OR l.penalty_served = 0)
+        AND (?7 IS NULL OR s.server_name = ?7 OR s.server_label = ?7)
+        AND (?8 IS NULL OR s.type IN (SELECT value FROM json_each(?8)))
+        AND (?9 IS NULL OR s.track IN (SELECT value FROM json_each(?9)))
+        AND (?10 IS NULL OR c.car_group IN (SELECT value FROM json_each(?10)))
+        ORDER BY s.track, s.wet, l.time_ms;
+    "#,
+        filter.class,
+        filter.model,
+        filter.track,
+        filter.from,
+        filter.until,
+        filter.exclude_penalty_laps,
+        filter.server,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
     )
     .fetch_all(conn)
     .await
     .unwrap()
 }
 
-async fn get_fastest_splits(conn: &mut SqliteConnection) -> HashMap<(String, i64), Vec<Duration>> {
-    sqlx::query!(
+async fn get_personal_best_laps_data(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> Vec<FastestLapQueryRow> {
+    sqlx::query_as!(
+        FastestLapQueryRow,
+        r#"
+        SELECT s.track,
+            s.wet AS "wet: bool",
+            l.id AS lap_id,
+            l.provisional AS "provisional: bool",
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            l.time_ms as laptime_ms,
+            c.model,
+            c.ballast_kg,
+            s.timestamp,
+            sp.time_ms AS sector_time_ms
+        FROM personal_best_laps pb
+        INNER JOIN laps l ON pb.lap_id = l.id
+        INNER JOIN sessions s ON l.session_id = s.id
+        INNER JOIN splits sp ON l.id = sp.lap_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers p ON pb.steam_id = p.steam_id
+        -- There's a personal best per car group, without a class filter only
+        -- the fastest of those counts.
+        WHERE pb.lap_id = (SELECT spb.lap_id
+                           FROM personal_best_laps spb
+                           WHERE spb.track = pb.track AND spb.wet = pb.wet
+                           AND spb.steam_id = pb.steam_id
+                           AND spb.exclude_penalty_laps = pb.exclude_penalty_laps
+                           AND (?1 IS NULL OR spb.car_group = ?1)
+                           AND (?4 IS NULL OR spb.session_type IN (SELECT value FROM json_each(?4)))
+                           AND (?6 IS NULL OR spb.car_group IN (SELECT value FROM json_each(?6)))
+                           ORDER BY spb.time_ms, spb.lap_id
+                           LIMIT 1)
+        AND (?1 IS NULL OR pb.car_group = ?1)
+        AND (?2 IS NULL OR pb.track = ?2)
+        AND pb.exclude_penalty_laps = ?3
+        AND (?4 IS NULL OR pb.session_type IN (SELECT value FROM json_each(?4)))
+        AND (?5 IS NULL OR pb.track IN (SELECT value FROM json_each(?5)))
+        AND (?6 IS NULL OR pb.car_group IN (SELECT value FROM json_each(?6)))
+        ORDER BY s.track, s.wet, l.time_ms;
+    "#,
+        filter.class,
+        filter.track,
+        filter.exclude_penalty_laps,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+pub(super) async fn get_fastest_splits(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> HashMap<(String, bool, i64), Vec<Duration>> {
+    let rows = if filter.uses_personal_bests() {
+        sqlx::query_as!(
+            FastestSplitQueryRow,
+            r#"
+            SELECT track AS "track!",
+                wet AS "wet!: bool",
+                steam_id AS "steam_id!",
+                MIN(time_ms) AS "sector_time_ms!: i64"
+            FROM personal_best_sectors
+            WHERE (?1 IS NULL OR car_group = ?1)
+            AND (?2 IS NULL OR track = ?2)
+            AND exclude_penalty_laps = ?3
+            AND (?4 IS NULL OR session_type IN (SELECT value FROM json_each(?4)))
+            AND (?5 IS NULL OR track IN (SELECT value FROM json_each(?5)))
+            AND (?6 IS NULL OR car_group IN (SELECT value FROM json_each(?6)))
+            GROUP BY track, wet, steam_id, sector
+            ORDER BY track, wet, steam_id, sector;
+        "#,
+            filter.class,
+            filter.track,
+            filter.exclude_penalty_laps,
+            filter.session_types,
+            filter.tracks,
+            filter.classes
+        )
+        .fetch_all(conn)
+        .await
+        .unwrap()
+    } else {
+        get_fastest_splits_from_laps(conn, filter).await
+    };
+    rows.into_iter()
+        .group_by(|row| (row.track.clone(), row.wet, row.steam_id))
+        .into_iter()
+        .map(|((track, wet, steam_id), rows)| {
+            let best_sectors = rows
+                .into_iter()
+                .map(|row| Duration::from_millis(row.sector_time_ms.try_into().unwrap()))
+                .collect::<Vec<_>>();
+            ((track, wet, steam_id), best_sectors)
+        })
+        .collect::<HashMap<_, _>>()
+}
+
+async fn get_fastest_splits_from_laps(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> Vec<FastestSplitQueryRow> {
+    sqlx::query_as!(
+        FastestSplitQueryRow,
         r#"
         SELECT s.track as "track!",
-            l.steam_id as "steam_id!",
-            sp.sector,
-            MIN(sp.time_ms) AS "sector_time_ms: i64"
+            s.wet as "wet!: bool",
+            COALESCE(a.merged_into, a.steam_id) as "steam_id!: i64",
+            MIN(sp.time_ms) AS "sector_time_ms!: i64"
         FROM splits sp
         INNER JOIN laps l ON sp.lap_id = l.id
         INNER JOIN sessions s ON l.session_id = s.id
-        WHERE l.valid = 1
-        GROUP BY s.track, l.steam_id, sp.sector
-        ORDER BY s.track, l.steam_id, sp.sector;
-    "#
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        AND (?1 IS NULL OR c.car_group = ?1)
+        AND (?2 IS NULL OR c.model = ?2)
+        AND (?3 IS NULL OR s.track = ?3)
+        AND (?4 IS NULL OR s.timestamp >= ?4)
+        AND (?5 IS NULL OR s.timestamp < ?5)
+        AND (?6 = 0 OR l.penalty_served = 0)
+        AND (?7 IS NULL OR s.server_name = ?7 OR s.server_label = ?7)
+        AND (?8 IS NULL OR s.type IN (SELECT value FROM json_each(?8)))
+        AND (?9 IS NULL OR s.track IN (SELECT value FROM json_each(?9)))
+        AND (?10 IS NULL OR c.car_group IN (SELECT value FROM json_each(?10)))
+        GROUP BY s.track, s.wet, COALESCE(a.merged_into, a.steam_id), sp.sector
+        ORDER BY s.track, s.wet, COALESCE(a.merged_into, a.steam_id), sp.sector;
+    "#,
+        filter.class,
+        filter.model,
+        filter.track,
+        filter.from,
+        filter.until,
+        filter.exclude_penalty_laps,
+        filter.server,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
     )
     .fetch_all(conn)
     .await
     .unwrap()
-    .into_iter()
-    .group_by(|row| (row.track.clone(), row.steam_id))
-    .into_iter()
-    .map(|((track, steam_id), rows)| {
-        let best_sectors = rows
-            .into_iter()
-            .map(|row| Duration::from_millis(row.sector_time_ms.try_into().unwrap()))
-            .collect::<Vec<_>>();
-        ((track, steam_id), best_sectors)
-    })
-    .collect::<HashMap<_, _>>()
 }
 
-async fn get_lap_counts(conn: &mut SqliteConnection) -> HashMap<(String, i64), (i64, i64)> {
+async fn get_lap_counts(
+    conn: &mut SqliteConnection,
+    filter: &BoardFilter,
+) -> HashMap<(String, bool, i64), (i64, i64)> {
     // Get valid and total laps for each driver for each track
     sqlx::query!(
         r#"
         SELECT s.track,
-            l.steam_id,
-            COUNT(1) FILTER (WHERE l.valid = 1) AS "valid_laps: i64",
+            s.wet AS "wet: bool",
+            COALESCE(a.merged_into, a.steam_id) AS "steam_id!: i64",
+            COUNT(1) FILTER (WHERE l.valid = 1 AND l.excluded = 0) AS "valid_laps: i64",
             COUNT(1) AS "total_laps: i64"
         FROM sessions s
         INNER JOIN laps l ON s.id = l.session_id
-        GROUP BY s.track, l.steam_id;
-        "#
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        WHERE (?1 IS NULL OR c.car_group = ?1)
+        AND (?2 IS NULL OR c.model = ?2)
+        AND (?3 IS NULL OR s.track = ?3)
+        AND (?4 IS NULL OR s.timestamp >= ?4)
+        AND (?5 IS NULL OR s.timestamp < ?5)
+        AND (?6 IS NULL OR s.server_name = ?6 OR s.server_label = ?6)
+        AND (?7 IS NULL OR s.type IN (SELECT value FROM json_each(?7)))
+        AND (?8 IS NULL OR s.track IN (SELECT value FROM json_each(?8)))
+        AND (?9 IS NULL OR c.car_group IN (SELECT value FROM json_each(?9)))
+        GROUP BY s.track, s.wet, COALESCE(a.merged_into, a.steam_id);
+        "#,
+        filter.class,
+        filter.model,
+        filter.track,
+        filter.from,
+        filter.until,
+        filter.server,
+        filter.session_types,
+        filter.tracks,
+        filter.classes
     )
     .fetch_all(conn)
     .await
@@ -433,10 +763,11 @@ async fn get_lap_counts(conn: &mut SqliteConnection) -> HashMap<(String, i64), (
     .into_iter()
     .map(|row| {
         let track = row.track;
+        let wet = row.wet;
         let steam_id = row.steam_id;
         let valid_laps = row.valid_laps;
         let total_laps = row.total_laps;
-        ((track, steam_id), (valid_laps, total_laps))
+        ((track, wet, steam_id), (valid_laps, total_laps))
     })
     .collect::<HashMap<_, _>>()
 }
@@ -0,0 +1,767 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{
+    extract,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use itertools::Itertools;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, NoneAsEmptyString};
+use sqlx::SqliteConnection;
+use std::{collections::HashMap, time::Duration};
+
+use super::{
+    car_group_to_display_name, car_model_to_display_name, driver_display_name, nationality_to_flag,
+    rootpage::get_servers, session_type_to_display_name, track_id_to_display_name,
+    DurationWithClass, State,
+};
+
+const SESSIONS_PER_PAGE: i64 = 50;
+
+fn cup_category_to_display_name(cup_category: i64) -> &'static str {
+    match cup_category {
+        0 => "Pro",
+        1 => "Pro-Am",
+        2 => "Am",
+        3 => "Silver",
+        4 => "National",
+        _ => "Unknown",
+    }
+}
+
+// Filters for the session index. Also used to build the links to other pages,
+// hence the Serialize. The filter form submits empty strings for "all".
+#[serde_as]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(crate) struct SessionsQuery {
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    track: Option<String>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    server: Option<String>,
+    #[serde_as(as = "NoneAsEmptyString")]
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    session_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<i64>,
+}
+
+impl SessionsQuery {
+    fn with_page(&self, page: i64) -> String {
+        let query = Self {
+            page: Some(page),
+            ..self.clone()
+        };
+        format!("?{}", serde_urlencoded::to_string(query).unwrap())
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SessionLine {
+    id: i64,
+    track: String,
+    track_name: String,
+    session_type: &'static str,
+    server_name: String,
+    server_label: Option<String>,
+    wet: bool,
+    timestamp: i64,
+    cars: i64,
+    laps: i64,
+    fastest_laptime: Option<DurationWithClass>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct IndexDisplayData {
+    sessions: Vec<SessionLine>,
+    page: i64,
+    pages: i64,
+    previous_page: Option<String>,
+    next_page: Option<String>,
+    tracks: Vec<(String, String)>,
+    servers: Vec<String>,
+    session_types: Vec<(String, &'static str)>,
+    query: SessionsQuery,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct CarLine {
+    race_number: i64,
+    car: String,
+    class: String,
+    cup_category: &'static str,
+    team_name: Option<String>,
+    ballast_kg: Option<i64>,
+    drivers: Vec<(i64, String)>,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct ResultDriver {
+    steam_id: i64,
+    name: String,
+    driving_time: DurationWithClass,
+    finished: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct ResultLine {
+    position: i64,
+    race_number: i64,
+    car: String,
+    class: String,
+    drivers: Vec<ResultDriver>,
+    lap_count: i64,
+    total_time: Option<DurationWithClass>,
+    best_lap: Option<DurationWithClass>,
+    gap: Option<DurationWithClass>,
+    laps_down: i64,
+    missing_mandatory_pitstop: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct PenaltyLine {
+    race_number: i64,
+    steam_id: Option<i64>,
+    name: Option<String>,
+    reason: String,
+    penalty: String,
+    penalty_value: i64,
+    violation_in_lap: i64,
+    cleared_in_lap: Option<i64>,
+    post_race: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct LapLine {
+    lap_id: i64,
+    lap_number: usize,
+    race_number: i64,
+    steam_id: i64,
+    name: String,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    laptime: DurationWithClass,
+    splits: Vec<DurationWithClass>,
+    valid: bool,
+    // Taken off the boards by a moderator
+    excluded: bool,
+    penalty_served: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayData {
+    provisional: bool,
+    id: i64,
+    track: String,
+    track_name: String,
+    session_type: &'static str,
+    server_name: String,
+    server_label: Option<String>,
+    wet: bool,
+    timestamp: i64,
+    fastest_lap_id: Option<i64>,
+    results: Vec<ResultLine>,
+    penalties: Vec<PenaltyLine>,
+    cars: Vec<CarLine>,
+    laps: Vec<LapLine>,
+}
+
+#[derive(Template)]
+#[template(path = "sessions.html")]
+struct IndexTemplate {
+    display_data: IndexDisplayData,
+}
+
+#[derive(Template)]
+#[template(path = "session.html")]
+struct SessionTemplate {
+    display_data: DisplayData,
+}
+
+struct SessionQueryRow {
+    id: i64,
+    track: String,
+    session_type: String,
+    server_name: String,
+    server_label: Option<String>,
+    wet: bool,
+    timestamp: i64,
+}
+
+struct SessionIndexQueryRow {
+    id: i64,
+    track: String,
+    session_type: String,
+    server_name: String,
+    server_label: Option<String>,
+    wet: bool,
+    timestamp: i64,
+    cars: i64,
+    laps: i64,
+    fastest_laptime_ms: Option<i64>,
+}
+
+struct CarQueryRow {
+    id: i64,
+    race_number: i64,
+    model: i64,
+    cup_category: i64,
+    car_group: String,
+    team_name: Option<String>,
+    ballast_kg: Option<i64>,
+}
+
+struct ResultQueryRow {
+    id: i64,
+    position: i64,
+    race_number: i64,
+    model: i64,
+    car_group: String,
+    lap_count: Option<i64>,
+    total_time_ms: Option<i64>,
+    best_lap_ms: Option<i64>,
+    missing_mandatory_pitstop: Option<i64>,
+    finishing_steam_id: Option<i64>,
+}
+
+struct ResultDriverQueryRow {
+    car_id: i64,
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    total_time_ms: i64,
+}
+
+struct PenaltyQueryRow {
+    race_number: i64,
+    steam_id: Option<i64>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    short_name: Option<String>,
+    reason: String,
+    penalty: String,
+    penalty_value: i64,
+    violation_in_lap: i64,
+    cleared_in_lap: i64,
+    post_race: bool,
+}
+
+struct LapQueryRow {
+    lap_id: i64,
+    car_id: i64,
+    race_number: i64,
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nationality: Option<i64>,
+    laptime_ms: i64,
+    valid: bool,
+    excluded: bool,
+    penalty_served: bool,
+    provisional: bool,
+    sector_time_ms: i64,
+}
+
+pub(crate) async fn index_handler(
+    extract::State(state): extract::State<State>,
+    Query(query): Query<SessionsQuery>,
+) -> impl IntoResponse {
+    debug!("sessions page, query {:?}", query);
+    let display_data = get_index_display_data(state, query).await.unwrap();
+    IndexTemplate { display_data }
+}
+
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Path(session_id): Path<i64>,
+) -> Response {
+    debug!("session page for {}", session_id);
+    match get_display_data(state, session_id).await.unwrap() {
+        Some(display_data) => SessionTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+pub(super) async fn get_index_display_data(
+    state: State,
+    query: SessionsQuery,
+) -> Result<IndexDisplayData> {
+    let mut conn = state.0.pool.acquire().await?;
+
+    let session_count = sqlx::query!(
+        r#"
+        SELECT COUNT(1) AS "count: i64"
+        FROM sessions s
+        WHERE (?1 IS NULL OR s.track = ?1)
+        AND (?2 IS NULL OR s.server_name = ?2 OR s.server_label = ?2)
+        AND (?3 IS NULL OR s.type = ?3);
+        "#,
+        query.track,
+        query.server,
+        query.session_type
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .count;
+    let pages = ((session_count + SESSIONS_PER_PAGE - 1) / SESSIONS_PER_PAGE).max(1);
+    let page = query.page.unwrap_or(1).clamp(1, pages);
+    let offset = (page - 1) * SESSIONS_PER_PAGE;
+
+    let sessions = sqlx::query_as!(
+        SessionIndexQueryRow,
+        r#"
+        SELECT s.id,
+            s.track,
+            s.type AS session_type,
+            s.server_name,
+            s.server_label,
+            s.wet AS "wet: bool",
+            s.timestamp,
+            (SELECT COUNT(1) FROM cars c WHERE c.session_id = s.id) AS "cars!: i64",
+            (SELECT COUNT(1) FROM laps l WHERE l.session_id = s.id) AS "laps!: i64",
+            (SELECT MIN(l.time_ms) FROM laps l WHERE l.session_id = s.id AND l.valid = 1 AND l.excluded = 0)
+                AS "fastest_laptime_ms: i64"
+        FROM sessions s
+        WHERE (?1 IS NULL OR s.track = ?1)
+        AND (?2 IS NULL OR s.server_name = ?2 OR s.server_label = ?2)
+        AND (?3 IS NULL OR s.type = ?3)
+        ORDER BY s.timestamp DESC
+        LIMIT ?4 OFFSET ?5;
+        "#,
+        query.track,
+        query.server,
+        query.session_type,
+        SESSIONS_PER_PAGE,
+        offset
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| SessionLine {
+        id: row.id,
+        track_name: track_id_to_display_name(&row.track),
+        track: row.track,
+        session_type: session_type_to_display_name(&row.session_type),
+        server_name: row.server_name,
+        server_label: row.server_label,
+        wet: row.wet,
+        timestamp: row.timestamp,
+        cars: row.cars,
+        laps: row.laps,
+        fastest_laptime: row
+            .fastest_laptime_ms
+            .map(|ms| DurationWithClass::new(Duration::from_millis(ms.try_into().unwrap()))),
+    })
+    .collect();
+
+    let tracks = sqlx::query!("SELECT DISTINCT track FROM sessions ORDER BY track;")
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let track_name = track_id_to_display_name(&row.track);
+            (row.track, track_name)
+        })
+        .collect();
+    let servers = get_servers(&state).await?;
+    let session_types =
+        sqlx::query!("SELECT DISTINCT type AS session_type FROM sessions ORDER BY type;")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| {
+                let display_name = session_type_to_display_name(&row.session_type);
+                (row.session_type, display_name)
+            })
+            .collect();
+
+    Ok(IndexDisplayData {
+        sessions,
+        page,
+        pages,
+        previous_page: (page > 1).then(|| query.with_page(page - 1)),
+        next_page: (page < pages).then(|| query.with_page(page + 1)),
+        tracks,
+        servers,
+        session_types,
+        query,
+    })
+}
+
+pub(super) async fn get_display_data(state: State, session_id: i64) -> Result<Option<DisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+
+    let Some(session) = get_session(&mut conn, session_id).await else {
+        return Ok(None);
+    };
+
+    let laps_data = get_laps_data(&mut conn, session_id).await;
+
+    // Drivers per car, in the order they first drove it
+    let mut drivers_per_car: HashMap<i64, Vec<(i64, String)>> = HashMap::new();
+    let mut laps_per_car: HashMap<i64, usize> = HashMap::new();
+    // Live timing sessions only have provisional laps
+    let provisional = laps_data.iter().any(|row| row.provisional);
+    let mut laps = laps_data
+        .into_iter()
+        .group_by(|row| row.lap_id)
+        .into_iter()
+        .map(|(_, rows)| {
+            let rows = rows.collect::<Vec<_>>();
+            let first = &rows[0];
+            let name = driver_display_name(&first.first_name, &first.last_name, &first.short_name);
+            let drivers = drivers_per_car.entry(first.car_id).or_default();
+            if !drivers
+                .iter()
+                .any(|(steam_id, _)| *steam_id == first.steam_id)
+            {
+                drivers.push((first.steam_id, name.clone()));
+            }
+            let lap_number = laps_per_car.entry(first.car_id).or_default();
+            *lap_number += 1;
+            let (flag_code, flag_name) = nationality_to_flag(first.nationality);
+            LapLine {
+                lap_id: first.lap_id,
+                lap_number: *lap_number,
+                race_number: first.race_number,
+                steam_id: first.steam_id,
+                name,
+                flag_code,
+                flag_name,
+                laptime: DurationWithClass::new(Duration::from_millis(
+                    first.laptime_ms.try_into().unwrap(),
+                )),
+                splits: rows
+                    .iter()
+                    .map(|row| {
+                        DurationWithClass::new(Duration::from_millis(
+                            row.sector_time_ms.try_into().unwrap(),
+                        ))
+                    })
+                    .collect(),
+                valid: first.valid,
+                excluded: first.excluded,
+                penalty_served: first.penalty_served,
+            }
+        })
+        .collect::<Vec<_>>();
+    set_purple_and_green(&mut laps);
+    let fastest_lap_id = laps
+        .iter()
+        .find(|lap| lap.laptime.class == "purple")
+        .map(|lap| lap.lap_id);
+
+    let cars = get_cars(&mut conn, session_id)
+        .await
+        .into_iter()
+        .map(|row| CarLine {
+            race_number: row.race_number,
+            car: car_model_to_display_name(row.model),
+            class: car_group_to_display_name(&row.car_group),
+            cup_category: cup_category_to_display_name(row.cup_category),
+            team_name: row.team_name.filter(|team_name| !team_name.is_empty()),
+            ballast_kg: row.ballast_kg,
+            drivers: drivers_per_car.remove(&row.id).unwrap_or_default(),
+        })
+        .collect();
+
+    let results = get_results(&mut conn, session_id, &session.session_type).await;
+
+    let penalties = get_penalties(&mut conn, session_id)
+        .await
+        .into_iter()
+        .map(|row| PenaltyLine {
+            race_number: row.race_number,
+            steam_id: row.steam_id,
+            name: match (row.first_name, row.last_name, row.short_name) {
+                (Some(first_name), Some(last_name), Some(short_name)) => {
+                    Some(driver_display_name(&first_name, &last_name, &short_name))
+                }
+                _ => None,
+            },
+            reason: row.reason,
+            penalty: row.penalty,
+            penalty_value: row.penalty_value,
+            violation_in_lap: row.violation_in_lap,
+            cleared_in_lap: Some(row.cleared_in_lap).filter(|lap| *lap > 0),
+            post_race: row.post_race,
+        })
+        .collect();
+
+    Ok(Some(DisplayData {
+        provisional,
+        id: session.id,
+        track_name: track_id_to_display_name(&session.track),
+        track: session.track,
+        session_type: session_type_to_display_name(&session.session_type),
+        server_name: session.server_name,
+        server_label: session.server_label,
+        wet: session.wet,
+        timestamp: session.timestamp,
+        fastest_lap_id,
+        results,
+        penalties,
+        cars,
+        laps,
+    }))
+}
+
+// Races are decided by laps completed and then total time, qualifying by best
+// lap. The gap is to the winner either way.
+async fn get_results(
+    conn: &mut SqliteConnection,
+    session_id: i64,
+    session_type: &str,
+) -> Vec<ResultLine> {
+    let mut drivers_per_car: HashMap<i64, Vec<ResultDriverQueryRow>> = HashMap::new();
+    for row in get_result_drivers(&mut *conn, session_id).await {
+        drivers_per_car.entry(row.car_id).or_default().push(row);
+    }
+    let race = session_type.starts_with('R');
+    let to_duration = |ms: i64| Duration::from_millis(ms.try_into().unwrap());
+    let mut winner: Option<(i64, Option<Duration>)> = None;
+    get_result_rows(conn, session_id)
+        .await
+        .into_iter()
+        .map(|row| {
+            let lap_count = row.lap_count.unwrap_or(0);
+            let total_time = row.total_time_ms.map(to_duration);
+            let best_lap = row.best_lap_ms.map(to_duration);
+            let time = if race { total_time } else { best_lap };
+            let (winner_laps, winner_time) = *winner.get_or_insert((lap_count, time));
+            let laps_down = if race { winner_laps - lap_count } else { 0 };
+            let gap = match (time, winner_time) {
+                (Some(time), Some(winner_time)) if row.position > 1 && laps_down == 0 => {
+                    Some(DurationWithClass::new(time.saturating_sub(winner_time)))
+                }
+                _ => None,
+            };
+            let drivers = drivers_per_car
+                .remove(&row.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|driver| ResultDriver {
+                    steam_id: driver.steam_id,
+                    name: driver_display_name(
+                        &driver.first_name,
+                        &driver.last_name,
+                        &driver.short_name,
+                    ),
+                    driving_time: DurationWithClass::new(to_duration(driver.total_time_ms)),
+                    finished: row.finishing_steam_id == Some(driver.steam_id),
+                })
+                .collect();
+            ResultLine {
+                position: row.position,
+                race_number: row.race_number,
+                car: car_model_to_display_name(row.model),
+                class: car_group_to_display_name(&row.car_group),
+                drivers,
+                lap_count,
+                total_time: total_time.map(DurationWithClass::new),
+                best_lap: best_lap.map(DurationWithClass::new),
+                gap,
+                laps_down,
+                missing_mandatory_pitstop: row.missing_mandatory_pitstop.unwrap_or(0) > 0,
+            }
+        })
+        .collect()
+}
+
+// Purple is the fastest in the session, green is a driver's personal best in
+// the session. Only valid laps that weren't excluded count towards either.
+fn set_purple_and_green(laps: &mut [LapLine]) {
+    let mut fastest_laptime = None;
+    let mut fastest_splits: Vec<Duration> = Vec::new();
+    let mut personal_fastest_laptimes: HashMap<i64, Duration> = HashMap::new();
+    let mut personal_fastest_splits: HashMap<i64, Vec<Duration>> = HashMap::new();
+    for lap in laps.iter().filter(|lap| lap.valid && !lap.excluded) {
+        let laptime = lap.laptime.duration;
+        fastest_laptime = Some(fastest_laptime.map_or(laptime, |f: Duration| f.min(laptime)));
+        personal_fastest_laptimes
+            .entry(lap.steam_id)
+            .and_modify(|f| *f = (*f).min(laptime))
+            .or_insert(laptime);
+        let personal_splits = personal_fastest_splits.entry(lap.steam_id).or_default();
+        for (index, split) in lap.splits.iter().enumerate() {
+            for splits in [&mut fastest_splits, &mut *personal_splits] {
+                match splits.get_mut(index) {
+                    Some(fastest) => *fastest = (*fastest).min(split.duration),
+                    None => splits.push(split.duration),
+                }
+            }
+        }
+    }
+    for lap in laps.iter_mut().filter(|lap| !lap.excluded) {
+        if fastest_laptime == Some(lap.laptime.duration) && lap.valid {
+            lap.laptime.class = "purple";
+        } else if personal_fastest_laptimes.get(&lap.steam_id) == Some(&lap.laptime.duration)
+            && lap.valid
+        {
+            lap.laptime.class = "green";
+        }
+        let personal_splits = personal_fastest_splits.get(&lap.steam_id);
+        for (index, split) in lap.splits.iter_mut().enumerate() {
+            if fastest_splits.get(index) == Some(&split.duration) {
+                split.class = "purple";
+            } else if personal_splits.and_then(|splits| splits.get(index)) == Some(&split.duration)
+            {
+                split.class = "green";
+            }
+        }
+    }
+}
+
+async fn get_session(conn: &mut SqliteConnection, session_id: i64) -> Option<SessionQueryRow> {
+    sqlx::query_as!(
+        SessionQueryRow,
+        r#"
+        SELECT id,
+            track,
+            type AS session_type,
+            server_name,
+            server_label,
+            wet AS "wet: bool",
+            timestamp
+        FROM sessions
+        WHERE id = ?;
+        "#,
+        session_id
+    )
+    .fetch_optional(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_cars(conn: &mut SqliteConnection, session_id: i64) -> Vec<CarQueryRow> {
+    sqlx::query_as!(
+        CarQueryRow,
+        r#"
+        SELECT id, race_number, model, cup_category, car_group, team_name, ballast_kg
+        FROM cars
+        WHERE session_id = ?
+        ORDER BY race_number;
+        "#,
+        session_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_result_rows(conn: &mut SqliteConnection, session_id: i64) -> Vec<ResultQueryRow> {
+    sqlx::query_as!(
+        ResultQueryRow,
+        r#"
+        SELECT id,
+            position AS "position!: i64",
+            race_number,
+            model,
+            car_group,
+            lap_count,
+            total_time_ms,
+            best_lap_ms,
+            missing_mandatory_pitstop,
+            finishing_steam_id
+        FROM cars
+        WHERE session_id = ? AND position IS NOT NULL
+        ORDER BY position;
+        "#,
+        session_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_result_drivers(
+    conn: &mut SqliteConnection,
+    session_id: i64,
+) -> Vec<ResultDriverQueryRow> {
+    sqlx::query_as!(
+        ResultDriverQueryRow,
+        r#"
+        SELECT cd.car_id,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            cd.total_time_ms
+        FROM car_drivers cd
+        INNER JOIN cars c ON cd.car_id = c.id
+        INNER JOIN drivers p ON cd.steam_id = p.steam_id
+        WHERE c.session_id = ?
+        ORDER BY cd.car_id, cd.driver_index;
+        "#,
+        session_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_penalties(conn: &mut SqliteConnection, session_id: i64) -> Vec<PenaltyQueryRow> {
+    sqlx::query_as!(
+        PenaltyQueryRow,
+        r#"
+        SELECT c.race_number,
+            pe.steam_id,
+            p.first_name AS "first_name?",
+            p.last_name AS "last_name?",
+            p.short_name AS "short_name?",
+            pe.reason,
+            pe.penalty,
+            pe.penalty_value,
+            pe.violation_in_lap,
+            pe.cleared_in_lap,
+            pe.post_race AS "post_race: bool"
+        FROM penalties pe
+        INNER JOIN cars c ON pe.car_id = c.id
+        LEFT JOIN drivers p ON pe.steam_id = p.steam_id
+        WHERE pe.session_id = ?
+        ORDER BY pe.post_race, pe.violation_in_lap, pe.id;
+        "#,
+        session_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+async fn get_laps_data(conn: &mut SqliteConnection, session_id: i64) -> Vec<LapQueryRow> {
+    sqlx::query_as!(
+        LapQueryRow,
+        r#"
+        SELECT l.id AS lap_id,
+            l.car_id,
+            c.race_number,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            l.time_ms AS laptime_ms,
+            l.valid AS "valid: bool",
+            l.excluded AS "excluded: bool",
+            l.penalty_served AS "penalty_served: bool",
+            l.provisional AS "provisional: bool",
+            sp.time_ms AS sector_time_ms
+        FROM laps l
+        INNER JOIN splits sp ON l.id = sp.lap_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers p ON l.steam_id = p.steam_id
+        WHERE l.session_id = ?
+        ORDER BY l.id, sp.sector;
+        "#,
+        session_id
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
@@ -0,0 +1,284 @@
+use anyhow::Result;
+use askama_axum::Template;
+use axum::{
+    extract,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use log::debug;
+use serde::Serialize;
+use sqlx::SqliteConnection;
+use std::time::Duration;
+
+use super::{
+    car_model_to_display_name, driver_display_name, nationality_to_flag, rootpage,
+    session_type_to_display_name, track_id_to_display_name, BoardFilter, DurationWithClass, State,
+};
+
+// A lap that was the track record at the time it was set
+#[derive(Clone, Serialize)]
+pub(super) struct RecordLine {
+    steam_id: i64,
+    name: String,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    laptime: DurationWithClass,
+    improvement: Option<DurationWithClass>,
+    car: String,
+    session_type: &'static str,
+    timestamp: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct SessionTypeLine {
+    session_type: &'static str,
+    wet: bool,
+    steam_id: i64,
+    name: String,
+    flag_code: &'static str,
+    flag_name: &'static str,
+    laptime: DurationWithClass,
+    car: String,
+    timestamp: i64,
+}
+
+#[derive(Clone, Serialize)]
+pub(super) struct DisplayData {
+    id: String,
+    name: String,
+    sessions: i64,
+    drivers: i64,
+    valid_laps: i64,
+    total_laps: i64,
+    boards: rootpage::DisplayData,
+    dry_records: Vec<RecordLine>,
+    wet_records: Vec<RecordLine>,
+    fastest_per_session_type: Vec<SessionTypeLine>,
+}
+
+#[derive(Template)]
+#[template(path = "track.html")]
+struct TrackTemplate {
+    display_data: DisplayData,
+}
+
+struct TrackStatsQueryRow {
+    sessions: i64,
+    drivers: i64,
+    valid_laps: i64,
+    total_laps: i64,
+}
+
+struct TrackLapQueryRow {
+    wet: bool,
+    session_type: String,
+    steam_id: i64,
+    first_name: String,
+    last_name: String,
+    short_name: String,
+    nationality: Option<i64>,
+    laptime_ms: i64,
+    model: i64,
+    timestamp: i64,
+}
+
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Path(track): Path<String>,
+    Query(filter): Query<BoardFilter>,
+) -> Response {
+    debug!("track page for {}, filter {:?}", track, filter);
+    match get_display_data(state, track, filter).await.unwrap() {
+        Some(display_data) => TrackTemplate { display_data }.into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found").into_response(),
+    }
+}
+
+pub(super) async fn get_display_data(
+    state: State,
+    track: String,
+    mut filter: BoardFilter,
+) -> Result<Option<DisplayData>> {
+    let mut conn = state.0.pool.acquire().await?;
+
+    let stats = get_track_stats(&mut conn, &track).await;
+    if stats.sessions == 0 {
+        return Ok(None);
+    }
+
+    let (dry_records, wet_records) = get_record_progression(&mut conn, &track).await;
+
+    let fastest_per_session_type = get_fastest_per_session_type(&mut conn, &track)
+        .await
+        .into_iter()
+        .map(|row| {
+            let (flag_code, flag_name) = nationality_to_flag(row.nationality);
+            let laptime = Duration::from_millis(row.laptime_ms.try_into().unwrap());
+            SessionTypeLine {
+                session_type: session_type_to_display_name(&row.session_type),
+                wet: row.wet,
+                steam_id: row.steam_id,
+                name: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+                flag_code,
+                flag_name,
+                laptime: DurationWithClass::new(laptime),
+                car: car_model_to_display_name(row.model),
+                timestamp: row.timestamp,
+            }
+        })
+        .collect();
+
+    filter.track = Some(track.clone());
+    let boards = rootpage::get_display_data(state, filter).await?;
+
+    Ok(Some(DisplayData {
+        name: track_id_to_display_name(&track),
+        id: track,
+        sessions: stats.sessions,
+        drivers: stats.drivers,
+        valid_laps: stats.valid_laps,
+        total_laps: stats.total_laps,
+        boards,
+        dry_records,
+        wet_records,
+        fastest_per_session_type,
+    }))
+}
+
+async fn get_track_stats(conn: &mut SqliteConnection, track: &str) -> TrackStatsQueryRow {
+    sqlx::query_as!(
+        TrackStatsQueryRow,
+        r#"
+        SELECT COUNT(DISTINCT s.id) AS "sessions!: i64",
+            COUNT(DISTINCT COALESCE(a.merged_into, a.steam_id)) AS "drivers!: i64",
+            COUNT(l.id) FILTER (WHERE l.valid = 1 AND l.excluded = 0) AS "valid_laps!: i64",
+            COUNT(l.id) AS "total_laps!: i64"
+        FROM sessions s
+        LEFT JOIN laps l ON s.id = l.session_id
+        LEFT JOIN drivers a ON l.steam_id = a.steam_id
+        WHERE s.track = ?;
+        "#,
+        track
+    )
+    .fetch_one(conn)
+    .await
+    .unwrap()
+}
+
+// Walks through all valid laps on the track in the order they were driven, and
+// keeps the ones that beat the record at that point in time.
+async fn get_record_progression(
+    conn: &mut SqliteConnection,
+    track: &str,
+) -> (Vec<RecordLine>, Vec<RecordLine>) {
+    let rows = sqlx::query_as!(
+        TrackLapQueryRow,
+        r#"
+        SELECT s.wet AS "wet: bool",
+            s.type AS session_type,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            l.time_ms AS laptime_ms,
+            c.model,
+            s.timestamp
+        FROM sessions s
+        INNER JOIN laps l ON s.id = l.session_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE s.track = ? AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        ORDER BY s.timestamp, l.id;
+        "#,
+        track
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap();
+
+    let mut dry_records: Vec<RecordLine> = Vec::new();
+    let mut wet_records: Vec<RecordLine> = Vec::new();
+    for row in rows {
+        let records = if row.wet {
+            &mut wet_records
+        } else {
+            &mut dry_records
+        };
+        let laptime = Duration::from_millis(row.laptime_ms.try_into().unwrap());
+        let previous_record = records.last().map(|record| record.laptime.duration);
+        if previous_record.is_some_and(|previous| laptime >= previous) {
+            continue;
+        }
+        let (flag_code, flag_name) = nationality_to_flag(row.nationality);
+        records.push(RecordLine {
+            steam_id: row.steam_id,
+            name: driver_display_name(&row.first_name, &row.last_name, &row.short_name),
+            flag_code,
+            flag_name,
+            laptime: DurationWithClass::new(laptime),
+            improvement: previous_record.map(|previous| DurationWithClass::new(previous - laptime)),
+            car: car_model_to_display_name(row.model),
+            session_type: session_type_to_display_name(&row.session_type),
+            timestamp: row.timestamp,
+        });
+    }
+    // The current record is the one people care about most, so show it first
+    dry_records.reverse();
+    wet_records.reverse();
+    if let Some(record) = dry_records.first_mut() {
+        record.laptime.class = "purple";
+    }
+    if let Some(record) = wet_records.first_mut() {
+        record.laptime.class = "purple";
+    }
+    (dry_records, wet_records)
+}
+
+async fn get_fastest_per_session_type(
+    conn: &mut SqliteConnection,
+    track: &str,
+) -> Vec<TrackLapQueryRow> {
+    sqlx::query_as!(
+        TrackLapQueryRow,
+        r#"
+        SELECT s.wet AS "wet: bool",
+            s.type AS session_type,
+            p.steam_id,
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            p.nationality,
+            l.time_ms AS laptime_ms,
+            c.model,
+            s.timestamp
+        FROM sessions s
+        INNER JOIN laps l ON s.id = l.session_id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        -- Same trick as the main leaderboard, but per session type instead of per driver.
+        WHERE l.id = (SELECT sl.id
+                      FROM laps sl
+                      INNER JOIN sessions ss ON sl.session_id = ss.id
+                      INNER JOIN drivers sa ON sl.steam_id = sa.steam_id
+                      INNER JOIN drivers sm ON COALESCE(sa.merged_into, sa.steam_id) = sm.steam_id
+                      WHERE ss.track = s.track AND ss.wet = s.wet AND ss.type = s.type
+                      AND sl.valid = 1 AND sl.excluded = 0
+                      AND sm.status = 'active'
+                      ORDER BY sl.time_ms, ss.timestamp
+                      LIMIT 1)
+        AND s.track = ?
+        AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        ORDER BY s.wet, s.type;
+        "#,
+        track
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
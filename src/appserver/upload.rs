@@ -0,0 +1,78 @@
+use anyhow::Context;
+use axum::{
+    body::Bytes,
+    extract::{self, Path},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::{info, warn};
+use serde::Serialize;
+use std::fs;
+use tokio::sync::Mutex;
+
+use super::{api::ApiError, constant_time_eq, State};
+use crate::{
+    check_file, config, filename_to_timestamp, is_results_filename, upload_dir, FileOutcome,
+};
+
+// Results files of long races get big, especially as UTF-16
+pub(super) const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+// One upload at a time, so the same file sent twice in a row can't end up in
+// the database twice
+static UPLOAD_LOCK: Mutex<()> = Mutex::const_new(());
+
+#[derive(Serialize)]
+struct UploadResponse {
+    filename: String,
+    #[serde(flatten)]
+    outcome: FileOutcome,
+}
+
+// Takes a results or entrylist file in the body, for servers that can't share
+// a directory with the boards. The file name has to be the one ACC gave it,
+// that's where the session timestamp comes from.
+pub(crate) async fn handler(
+    extract::State(state): extract::State<State>,
+    Path(filename): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let upload = config::get().upload.as_ref().ok_or(ApiError::NotFound)?;
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), upload.token.as_bytes())) {
+        warn!("Upload of {} with wrong or missing token", filename);
+        return Err(ApiError::Unauthorized);
+    }
+    // Checking the format also rules out anything with a path in it
+    if !is_results_filename(&filename) || filename_to_timestamp(&filename).is_err() {
+        return Err(ApiError::BadRequest(format!(
+            "{filename} is not named like an ACC results or entrylist file"
+        )));
+    }
+    info!("Upload of {} ({} bytes)", filename, body.len());
+
+    let _lock = UPLOAD_LOCK.lock().await;
+    let results_dir = upload_dir()?.context("Uploads not configured")?;
+    let path = results_dir.path.join(&filename);
+    let mut conn = state.0.pool.acquire().await.map_err(anyhow::Error::from)?;
+    // Sending the same file again is fine, it just won't do anything. A
+    // different one replaces what came from the earlier upload, same as when
+    // ACC writes a results file again.
+    if fs::read(&path).ok().is_none_or(|existing| existing != body) {
+        fs::write(&path, &body).context("Failed to store upload")?;
+    }
+
+    let outcome = check_file(&path, &results_dir, &mut conn, &state.0.events).await?;
+    let status = match outcome {
+        FileOutcome::Unreadable { .. } | FileOutcome::Quarantined => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        _ => StatusCode::OK,
+    };
+    Ok((status, Json(UploadResponse { filename, outcome })).into_response())
+}
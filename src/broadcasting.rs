@@ -0,0 +1,459 @@
+use anyhow::{anyhow, Result};
+
+// ACC's broadcasting protocol, as used by the official broadcasting SDK. All
+// numbers are little endian, strings are a u16 byte length followed by UTF-8.
+// Only the parts needed for live timing are kept, the rest is skipped over.
+
+const PROTOCOL_VERSION: u8 = 4;
+
+// Used by ACC for times and splits it doesn't have (yet)
+const NO_TIME: i32 = i32::MAX;
+
+const REGISTER_COMMAND_APPLICATION: u8 = 1;
+const UNREGISTER_COMMAND_APPLICATION: u8 = 9;
+const REQUEST_ENTRY_LIST: u8 = 10;
+const REQUEST_TRACK_DATA: u8 = 11;
+
+const REGISTRATION_RESULT: u8 = 1;
+const REALTIME_UPDATE: u8 = 2;
+const REALTIME_CAR_UPDATE: u8 = 3;
+const ENTRY_LIST: u8 = 4;
+const TRACK_DATA: u8 = 5;
+const ENTRY_LIST_CAR: u8 = 6;
+const BROADCASTING_EVENT: u8 = 7;
+
+const LAP_COMPLETED_EVENT: u8 = 5;
+
+#[derive(Debug)]
+pub struct LapInfo {
+    pub laptime_ms: Option<i32>,
+    pub driver_index: u16,
+    pub splits: Vec<Option<i32>>,
+    pub is_invalid: bool,
+    pub is_valid_for_best: bool,
+}
+
+#[derive(Debug)]
+pub struct RealtimeUpdate {
+    pub session_index: u16,
+    pub session_type: u8,
+    pub wetness: u8,
+}
+
+#[derive(Debug)]
+pub struct RealtimeCarUpdate {
+    pub car_index: u16,
+    pub laps: u16,
+    pub last_lap: LapInfo,
+}
+
+#[derive(Clone, Debug)]
+pub struct DriverInfo {
+    pub first_name: String,
+    pub last_name: String,
+    pub short_name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct EntryListCar {
+    pub car_index: u16,
+    pub car_model: u8,
+    pub team_name: String,
+    pub race_number: i32,
+    pub cup_category: u8,
+    pub drivers: Vec<DriverInfo>,
+}
+
+#[derive(Debug)]
+pub enum InboundMessage {
+    RegistrationResult {
+        connection_id: i32,
+        success: bool,
+        error_message: String,
+    },
+    RealtimeUpdate(RealtimeUpdate),
+    RealtimeCarUpdate(RealtimeCarUpdate),
+    EntryList,
+    EntryListCar(EntryListCar),
+    TrackData {
+        track_name: String,
+    },
+    LapCompleted {
+        car_index: i32,
+        message: String,
+    },
+    // Anything else, including broadcasting events other than lap completed
+    Other(u8),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(anyhow!("Broadcasting packet too short"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? > 0)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn skip_f32(&mut self) -> Result<()> {
+        self.take(4)?;
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let length = self.u16()?;
+        Ok(String::from_utf8_lossy(self.take(length.into())?).into_owned())
+    }
+
+    fn lap(&mut self) -> Result<LapInfo> {
+        let laptime_ms = Some(self.i32()?).filter(|time| *time != NO_TIME);
+        let _car_index = self.u16()?;
+        let driver_index = self.u16()?;
+        let split_count = self.u8()?;
+        let splits = (0..split_count)
+            .map(|_| Ok(Some(self.i32()?).filter(|time| *time != NO_TIME)))
+            .collect::<Result<Vec<_>>>()?;
+        let is_invalid = self.bool()?;
+        let is_valid_for_best = self.bool()?;
+        // Out lap and in lap flags
+        self.take(2)?;
+        Ok(LapInfo {
+            laptime_ms,
+            driver_index,
+            splits,
+            is_invalid,
+            is_valid_for_best,
+        })
+    }
+}
+
+pub fn parse_message(bytes: &[u8]) -> Result<InboundMessage> {
+    let mut reader = Reader { bytes };
+    let message_type = reader.u8()?;
+    Ok(match message_type {
+        REGISTRATION_RESULT => {
+            let connection_id = reader.i32()?;
+            let success = reader.bool()?;
+            let _readonly = reader.u8()?;
+            let error_message = reader.string()?;
+            InboundMessage::RegistrationResult {
+                connection_id,
+                success,
+                error_message,
+            }
+        }
+        REALTIME_UPDATE => {
+            let _event_index = reader.u16()?;
+            let session_index = reader.u16()?;
+            let session_type = reader.u8()?;
+            let _phase = reader.u8()?;
+            // Session time and session end time
+            reader.skip_f32()?;
+            reader.skip_f32()?;
+            let _focused_car_index = reader.i32()?;
+            // Camera set, camera and HUD page
+            for _ in 0..3 {
+                reader.string()?;
+            }
+            if reader.bool()? {
+                // Replay session time and remaining time
+                reader.skip_f32()?;
+                reader.skip_f32()?;
+            }
+            // Time of day
+            reader.skip_f32()?;
+            // Ambient temperature, track temperature, clouds and rain level
+            reader.take(4)?;
+            let wetness = reader.u8()?;
+            InboundMessage::RealtimeUpdate(RealtimeUpdate {
+                session_index,
+                session_type,
+                wetness,
+            })
+        }
+        REALTIME_CAR_UPDATE => {
+            let car_index = reader.u16()?;
+            // Driver index, driver count and gear
+            reader.take(4)?;
+            // World position X and Y, and yaw
+            for _ in 0..3 {
+                reader.skip_f32()?;
+            }
+            // Car location, speed, position, cup position and track position
+            reader.take(9)?;
+            // Spline position
+            reader.skip_f32()?;
+            let laps = reader.u16()?;
+            let _delta = reader.i32()?;
+            let _best_session_lap = reader.lap()?;
+            let last_lap = reader.lap()?;
+            InboundMessage::RealtimeCarUpdate(RealtimeCarUpdate {
+                car_index,
+                laps,
+                last_lap,
+            })
+        }
+        ENTRY_LIST => InboundMessage::EntryList,
+        TRACK_DATA => {
+            let _connection_id = reader.i32()?;
+            let track_name = reader.string()?;
+            InboundMessage::TrackData { track_name }
+        }
+        ENTRY_LIST_CAR => {
+            let car_index = reader.u16()?;
+            let car_model = reader.u8()?;
+            let team_name = reader.string()?;
+            let race_number = reader.i32()?;
+            let cup_category = reader.u8()?;
+            let _current_driver_index = reader.u8()?;
+            let _nationality = reader.u16()?;
+            let driver_count = reader.u8()?;
+            let drivers = (0..driver_count)
+                .map(|_| {
+                    let driver = DriverInfo {
+                        first_name: reader.string()?,
+                        last_name: reader.string()?,
+                        short_name: reader.string()?,
+                    };
+                    // Category and nationality
+                    reader.take(3)?;
+                    Ok(driver)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            InboundMessage::EntryListCar(EntryListCar {
+                car_index,
+                car_model,
+                team_name,
+                race_number,
+                cup_category,
+                drivers,
+            })
+        }
+        BROADCASTING_EVENT => {
+            let event_type = reader.u8()?;
+            let message = reader.string()?;
+            let _time_ms = reader.i32()?;
+            let car_index = reader.i32()?;
+            if event_type == LAP_COMPLETED_EVENT {
+                InboundMessage::LapCompleted { car_index, message }
+            } else {
+                InboundMessage::Other(message_type)
+            }
+        }
+        _ => InboundMessage::Other(message_type),
+    })
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend_from_slice(&u16::try_from(string.len()).unwrap().to_le_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+}
+
+pub fn register_request(
+    display_name: &str,
+    connection_password: &str,
+    update_interval_ms: i32,
+    command_password: &str,
+) -> Vec<u8> {
+    let mut bytes = vec![REGISTER_COMMAND_APPLICATION, PROTOCOL_VERSION];
+    write_string(&mut bytes, display_name);
+    write_string(&mut bytes, connection_password);
+    bytes.extend_from_slice(&update_interval_ms.to_le_bytes());
+    write_string(&mut bytes, command_password);
+    bytes
+}
+
+fn connection_request(message_type: u8, connection_id: i32) -> Vec<u8> {
+    let mut bytes = vec![message_type];
+    bytes.extend_from_slice(&connection_id.to_le_bytes());
+    bytes
+}
+
+pub fn unregister_request(connection_id: i32) -> Vec<u8> {
+    connection_request(UNREGISTER_COMMAND_APPLICATION, connection_id)
+}
+
+pub fn entry_list_request(connection_id: i32) -> Vec<u8> {
+    connection_request(REQUEST_ENTRY_LIST, connection_id)
+}
+
+pub fn track_data_request(connection_id: i32) -> Vec<u8> {
+    connection_request(REQUEST_TRACK_DATA, connection_id)
+}
+
+// Session types as ACC numbers them, mapped to the letters used in the
+// results files. Hotlap and superpole variants are close enough to qualifying.
+pub fn session_type_to_results_type(session_type: u8) -> &'static str {
+    match session_type {
+        4 | 9 | 11 | 13 => "Q",
+        10 => "R",
+        _ => "P",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A short recording in the LIVE_TIMING_RECORD format: registration, track
+    // data, the entry list with two cars, a realtime update, three realtime car
+    // updates and two broadcasting events.
+    const CAPTURE: &[u8] = include_bytes!("../tests/data/broadcast_capture.bin");
+
+    fn capture() -> Vec<InboundMessage> {
+        let mut messages = Vec::new();
+        let mut rest = CAPTURE;
+        while !rest.is_empty() {
+            let (length, remainder) = rest.split_at(4);
+            let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+            let (packet, remainder) = remainder.split_at(length);
+            messages.push(parse_message(packet).unwrap());
+            rest = remainder;
+        }
+        messages
+    }
+
+    #[test]
+    fn registration_result() {
+        let InboundMessage::RegistrationResult {
+            connection_id,
+            success,
+            error_message,
+        } = &capture()[0]
+        else {
+            panic!("not a registration result");
+        };
+        assert_eq!(*connection_id, 7);
+        assert!(success);
+        assert_eq!(error_message, "");
+    }
+
+    #[test]
+    fn track_data() {
+        let InboundMessage::TrackData { track_name } = &capture()[1] else {
+            panic!("not track data");
+        };
+        assert_eq!(track_name, "Spa-Francorchamps");
+    }
+
+    #[test]
+    fn entry_list() {
+        let messages = capture();
+        assert!(matches!(messages[2], InboundMessage::EntryList));
+        let cars = messages[3..5]
+            .iter()
+            .map(|message| match message {
+                InboundMessage::EntryListCar(car) => car,
+                _ => panic!("not an entry list car"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cars[0].car_index, 1001);
+        assert_eq!(cars[0].car_model, 30);
+        assert_eq!(cars[0].team_name, "Team Hotlap");
+        assert_eq!(cars[0].race_number, 12);
+        assert_eq!(cars[0].drivers.len(), 1);
+        assert_eq!(cars[0].drivers[0].first_name, "Max");
+        assert_eq!(cars[0].drivers[0].last_name, "Example");
+        assert_eq!(cars[0].drivers[0].short_name, "EXA");
+        let drivers = cars[1]
+            .drivers
+            .iter()
+            .map(|driver| driver.short_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(drivers, ["SAM", "TES"]);
+    }
+
+    #[test]
+    fn realtime_update() {
+        let InboundMessage::RealtimeUpdate(update) = &capture()[5] else {
+            panic!("not a realtime update");
+        };
+        assert_eq!(update.session_index, 0);
+        assert_eq!(update.session_type, 4);
+        assert_eq!(update.wetness, 0);
+    }
+
+    #[test]
+    fn realtime_car_updates() {
+        let updates = capture()
+            .into_iter()
+            .filter_map(|message| match message {
+                InboundMessage::RealtimeCarUpdate(update) => Some(update),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(updates.len(), 3);
+
+        let first = &updates[0];
+        assert_eq!((first.car_index, first.laps), (1001, 3));
+        assert_eq!(first.last_lap.laptime_ms, Some(139_512));
+        assert_eq!(
+            first.last_lap.splits,
+            [Some(45_001), Some(53_210), Some(41_301)]
+        );
+        assert!(!first.last_lap.is_invalid);
+        assert!(first.last_lap.is_valid_for_best);
+
+        // Invalid lap by the second driver of the car, ACC has no times for
+        // its last two sectors
+        let invalid = &updates[1];
+        assert_eq!((invalid.car_index, invalid.laps), (1002, 2));
+        assert_eq!(invalid.last_lap.driver_index, 1);
+        assert_eq!(invalid.last_lap.splits, [Some(46_000), None, None]);
+        assert!(invalid.last_lap.is_invalid);
+        assert!(!invalid.last_lap.is_valid_for_best);
+
+        let next = &updates[2];
+        assert_eq!((next.car_index, next.laps), (1001, 4));
+        assert_eq!(next.last_lap.laptime_ms, Some(138_990));
+    }
+
+    #[test]
+    fn broadcasting_events() {
+        let messages = capture();
+        let InboundMessage::LapCompleted { car_index, message } = &messages[9] else {
+            panic!("not a lap completed event");
+        };
+        assert_eq!(*car_index, 1001);
+        assert_eq!(message, "Lap completed");
+        assert!(matches!(
+            messages[10],
+            InboundMessage::Other(BROADCASTING_EVENT)
+        ));
+    }
+
+    #[test]
+    fn truncated_packet() {
+        let length = u32::from_le_bytes(CAPTURE[..4].try_into().unwrap()) as usize;
+        assert!(parse_message(&CAPTURE[4..4 + length - 1]).is_err());
+    }
+
+    #[test]
+    fn session_types() {
+        // Practice, qualifying, superpole, race, hotlap, hotstint, hotlap
+        // superpole and replay
+        let types = [0, 4, 9, 10, 11, 12, 13, 14].map(session_type_to_results_type);
+        assert_eq!(types, ["P", "Q", "Q", "R", "Q", "P", "Q", "P"]);
+    }
+}
@@ -0,0 +1,549 @@
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use serde::Deserialize;
+use std::{
+    env, fs,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+// Used when there's no `--config` on the command line. Not having this file
+// is fine, everything can come from the environment as well.
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+const SESSION_TYPES: [&str; 3] = ["P", "Q", "R"];
+const CAR_GROUPS: [&str; 7] = ["GT3", "GT4", "GT2", "CUP", "ST", "CHL", "TCX"];
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database_url: Option<String>,
+    pub bind_address: String,
+    pub results: Vec<ResultsDirConfig>,
+    pub site: SiteConfig,
+    pub boards: BoardsConfig,
+    pub cache: CacheConfig,
+    pub live_timing: Option<LiveTimingConfig>,
+    pub upload: Option<UploadConfig>,
+    pub admin: Option<AdminConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResultsDirConfig {
+    pub path: PathBuf,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SiteConfig {
+    pub title: String,
+    // Image file shown at the top of every page, instead of the built-in one
+    pub header_logo: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoardsConfig {
+    // Laps from other session types don't make it onto the boards
+    pub session_types: Vec<String>,
+    // Empty means all of them
+    pub tracks: Vec<String>,
+    pub classes: Vec<String>,
+    pub exclude_penalty_laps: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    // Cached pages are always dropped when new laps come in, this drops them
+    // after a while on top of that, for what the import subcommands change
+    // behind the server's back. 0 means never.
+    pub max_age_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LiveTimingConfig {
+    pub address: String,
+    #[serde(default)]
+    pub password: String,
+    pub server_name: String,
+    pub record: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadConfig {
+    // Sent by the uploader as `Authorization: Bearer <token>`
+    pub token: String,
+    // Uploaded files are kept here, so that a reimport can find them again.
+    // Not watched, so it can't be one of the results directories.
+    pub directory: PathBuf,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    // Either one logs in on the login page. The token also works without
+    // logging in, as `Authorization: Bearer <token>`, for scripts.
+    pub password: Option<String>,
+    pub token: Option<String>,
+    // For sites served over HTTPS, so that browsers only send the login
+    // cookie over HTTPS
+    #[serde(default)]
+    pub secure_cookie: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: None,
+            bind_address: "127.0.0.1:3000".to_string(),
+            results: Vec::new(),
+            site: SiteConfig::default(),
+            boards: BoardsConfig::default(),
+            cache: CacheConfig::default(),
+            live_timing: None,
+            upload: None,
+            admin: None,
+        }
+    }
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        Self {
+            title: "Offline Racing ACC compo stats".to_string(),
+            header_logo: None,
+        }
+    }
+}
+
+impl Default for BoardsConfig {
+    fn default() -> Self {
+        Self {
+            session_types: SESSION_TYPES.iter().map(ToString::to_string).collect(),
+            tracks: Vec::new(),
+            classes: Vec::new(),
+            exclude_penalty_laps: false,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age_secs: 300,
+        }
+    }
+}
+
+// Reads the config file, applies the environment on top of it and checks the
+// result. Has to be called before `get`.
+pub fn load(path: Option<&Path>) -> Result<()> {
+    let mut config = match path {
+        Some(path) => read(path)?,
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => read(Path::new(DEFAULT_CONFIG_FILE))?,
+        None => Config::default(),
+    };
+    config.apply_env()?;
+    config.validate()?;
+    CONFIG
+        .set(config)
+        .map_err(|_| anyhow!("Configuration loaded twice"))
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("Configuration not loaded")
+}
+
+// Going by the extension is good enough for a file the admin picked
+pub fn image_content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "svg" => Some("image/svg+xml"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+fn read(path: &Path) -> Result<Config> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+fn env_bool(name: &str) -> Result<Option<bool>> {
+    env::var(name)
+        .ok()
+        .map(|value| match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" => Ok(true),
+            "0" | "false" | "no" | "" => Ok(false),
+            _ => Err(anyhow!("{name} must be true or false, not {value:?}")),
+        })
+        .transpose()
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    env::var(name).ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToString::to_string)
+            .collect()
+    })
+}
+
+// `RESULTS_PATH` is a `;` separated list of directories, each of which can
+// have a label in front of it, separated by `=`. For example
+// `Hotlap=/srv/hotlap/results;League=/srv/league/results`.
+fn parse_results_path(results_path: &str) -> Vec<ResultsDirConfig> {
+    results_path
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((label, path)) => ResultsDirConfig {
+                path: path.trim().into(),
+                label: Some(label.trim().to_string()),
+            },
+            None => ResultsDirConfig {
+                path: entry.into(),
+                label: None,
+            },
+        })
+        .collect()
+}
+
+impl Config {
+    // Environment variables win over the config file, so that `.env` files
+    // from before there was a config file keep working.
+    fn apply_env(&mut self) -> Result<()> {
+        if let Ok(database_url) = env::var("DATABASE_URL") {
+            self.database_url = Some(database_url);
+        }
+        if let Ok(bind_address) = env::var("BIND_ADDRESS") {
+            self.bind_address = bind_address;
+        }
+        if let Ok(results_path) = env::var("RESULTS_PATH") {
+            self.results = parse_results_path(&results_path);
+        }
+        if let Ok(title) = env::var("SITE_TITLE") {
+            self.site.title = title;
+        }
+        if let Ok(header_logo) = env::var("HEADER_LOGO") {
+            self.site.header_logo = (!header_logo.is_empty()).then(|| header_logo.into());
+        }
+        if let Some(session_types) = env_list("SESSION_TYPES") {
+            self.boards.session_types = session_types;
+        }
+        if let Some(tracks) = env_list("TRACKS") {
+            self.boards.tracks = tracks;
+        }
+        if let Some(classes) = env_list("CLASSES") {
+            self.boards.classes = classes;
+        }
+        if let Some(exclude_penalty_laps) = env_bool("EXCLUDE_PENALTY_LAPS")? {
+            self.boards.exclude_penalty_laps = exclude_penalty_laps;
+        }
+        if let Some(enabled) = env_bool("CACHE_ENABLED")? {
+            self.cache.enabled = enabled;
+        }
+        if let Ok(max_age_secs) = env::var("CACHE_MAX_AGE_SECS") {
+            self.cache.max_age_secs = max_age_secs.parse().with_context(|| {
+                format!("CACHE_MAX_AGE_SECS must be a number, not {max_age_secs:?}")
+            })?;
+        }
+        if let Ok(address) = env::var("LIVE_TIMING_ADDRESS") {
+            let live_timing = self.live_timing.get_or_insert(LiveTimingConfig {
+                address: String::new(),
+                password: String::new(),
+                server_name: String::new(),
+                record: None,
+            });
+            live_timing.address = address;
+        }
+        if let Some(live_timing) = self.live_timing.as_mut() {
+            if let Ok(password) = env::var("LIVE_TIMING_PASSWORD") {
+                live_timing.password = password;
+            }
+            if let Ok(server_name) = env::var("LIVE_TIMING_SERVER_NAME") {
+                live_timing.server_name = server_name;
+            }
+            if let Ok(record) = env::var("LIVE_TIMING_RECORD") {
+                live_timing.record = Some(record.into());
+            }
+        }
+        if let Ok(token) = env::var("UPLOAD_TOKEN") {
+            let upload = self.upload.get_or_insert(UploadConfig {
+                token: String::new(),
+                directory: PathBuf::new(),
+                label: None,
+            });
+            upload.token = token;
+        }
+        if let Some(upload) = self.upload.as_mut() {
+            if let Ok(directory) = env::var("UPLOAD_DIRECTORY") {
+                upload.directory = directory.into();
+            }
+            if let Ok(label) = env::var("UPLOAD_LABEL") {
+                upload.label = Some(label);
+            }
+        }
+        if let Ok(password) = env::var("ADMIN_PASSWORD") {
+            self.admin.get_or_insert_with(AdminConfig::default).password = Some(password);
+        }
+        if let Ok(token) = env::var("ADMIN_TOKEN") {
+            self.admin.get_or_insert_with(AdminConfig::default).token = Some(token);
+        }
+        if let Some(secure_cookie) = env_bool("ADMIN_SECURE_COOKIE")? {
+            if let Some(admin) = self.admin.as_mut() {
+                admin.secure_cookie = secure_cookie;
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.bind_address.to_socket_addrs().with_context(|| {
+            format!(
+                "bind_address {:?} is not a valid address",
+                self.bind_address
+            )
+        })?;
+        for results_dir in &self.results {
+            if !results_dir.path.is_dir() {
+                bail!(
+                    "Results directory {} does not exist or is not a directory",
+                    results_dir.path.display()
+                );
+            }
+            if results_dir.label.as_deref() == Some("") {
+                bail!(
+                    "Results directory {} has an empty label",
+                    results_dir.path.display()
+                );
+            }
+        }
+        if self.site.title.trim().is_empty() {
+            bail!("site.title can't be empty");
+        }
+        if let Some(header_logo) = &self.site.header_logo {
+            if !header_logo.is_file() {
+                bail!("site.header_logo {} is not a file", header_logo.display());
+            }
+            if image_content_type(header_logo).is_none() {
+                bail!(
+                    "site.header_logo {} must be a PNG, JPEG, GIF, SVG or WebP image",
+                    header_logo.display()
+                );
+            }
+        }
+        if self.boards.session_types.is_empty() {
+            bail!("boards.session_types can't be empty, no laps would count");
+        }
+        for session_type in &self.boards.session_types {
+            if !SESSION_TYPES.contains(&session_type.as_str()) {
+                bail!(
+                    "boards.session_types has {session_type:?}, but only {} exist",
+                    SESSION_TYPES.join(", ")
+                );
+            }
+        }
+        for class in &self.boards.classes {
+            // Might be a car group that was added to the game later
+            if !CAR_GROUPS.contains(&class.as_str()) {
+                warn!("boards.classes has unknown car group {:?}", class);
+            }
+        }
+        if let Some(live_timing) = &self.live_timing {
+            live_timing.address.to_socket_addrs().with_context(|| {
+                format!(
+                    "live_timing.address {:?} is not a valid address",
+                    live_timing.address
+                )
+            })?;
+            // Has to match the results files, or provisional sessions never
+            // get replaced
+            if live_timing.server_name.is_empty() {
+                bail!("live_timing.server_name must be set for live timing");
+            }
+        }
+        if let Some(upload) = &self.upload {
+            // Anything shorter is too easy to guess
+            if upload.token.len() < 16 {
+                bail!("upload.token must be at least 16 characters");
+            }
+            if !upload.directory.is_dir() {
+                bail!(
+                    "upload.directory {} does not exist or is not a directory",
+                    upload.directory.display()
+                );
+            }
+            let directory = fs::canonicalize(&upload.directory)?;
+            for results_dir in &self.results {
+                if fs::canonicalize(&results_dir.path)? == directory {
+                    bail!(
+                        "upload.directory {} is also a results directory",
+                        upload.directory.display()
+                    );
+                }
+            }
+            if upload.label.as_deref() == Some("") {
+                bail!("upload.label can't be empty");
+            }
+        }
+        if let Some(admin) = &self.admin {
+            if admin.password.is_none() && admin.token.is_none() {
+                bail!("admin needs a password or a token");
+            }
+            if admin
+                .password
+                .as_ref()
+                .is_some_and(|password| password.len() < 8)
+            {
+                bail!("admin.password must be at least 8 characters");
+            }
+            if admin.token.as_ref().is_some_and(|token| token.len() < 16) {
+                bail!("admin.token must be at least 16 characters");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn database_url(&self) -> Result<&str> {
+        self.database_url
+            .as_deref()
+            .context("database_url must be set, in the config file or as DATABASE_URL")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_path() {
+        let results = parse_results_path(" Hotlap = /srv/hotlap ;/srv/league;; ");
+        let results = results
+            .iter()
+            .map(|results_dir| (results_dir.label.as_deref(), results_dir.path.to_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [
+                (Some("Hotlap"), Some("/srv/hotlap")),
+                (None, Some("/srv/league"))
+            ]
+        );
+        assert!(parse_results_path("").is_empty());
+    }
+
+    // The only test that touches the environment, tests run in parallel
+    #[test]
+    fn environment_overrides() {
+        env::set_var("RESULTS_PATH", "Hotlap=/srv/hotlap;/srv/league");
+        env::set_var("SESSION_TYPES", "Q, R");
+        env::set_var("EXCLUDE_PENALTY_LAPS", "yes");
+        let mut config: Config = toml::from_str(
+            r#"
+            [[results]]
+            path = "/srv/from_file"
+            "#,
+        )
+        .unwrap();
+        config.apply_env().unwrap();
+        let paths = config
+            .results
+            .iter()
+            .map(|results_dir| results_dir.path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/srv/hotlap", "/srv/league"]);
+        assert_eq!(config.results[0].label.as_deref(), Some("Hotlap"));
+        assert_eq!(config.boards.session_types, ["Q", "R"]);
+        assert!(config.boards.exclude_penalty_laps);
+
+        env::set_var("EXCLUDE_PENALTY_LAPS", "sometimes");
+        assert!(Config::default().apply_env().is_err());
+        env::remove_var("EXCLUDE_PENALTY_LAPS");
+        env::set_var("CACHE_MAX_AGE_SECS", "soon");
+        assert!(Config::default().apply_env().is_err());
+
+        for name in ["RESULTS_PATH", "SESSION_TYPES", "CACHE_MAX_AGE_SECS"] {
+            env::remove_var(name);
+        }
+    }
+
+    fn results_dir(path: &Path, label: Option<&str>) -> ResultsDirConfig {
+        ResultsDirConfig {
+            path: path.to_path_buf(),
+            label: label.map(ToString::to_string),
+        }
+    }
+
+    #[test]
+    fn validation() {
+        let directory = env::temp_dir();
+        assert!(Config::default().validate().is_ok());
+
+        let mut config = Config {
+            results: vec![results_dir(&directory, Some("Hotlap"))],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        config.results = vec![results_dir(&directory, Some(""))];
+        assert!(config.validate().is_err());
+        config.results = vec![results_dir(&directory.join("no such directory"), None)];
+        assert!(config.validate().is_err());
+
+        let config = Config {
+            bind_address: "not an address".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.boards.session_types = vec!["X".to_string()];
+        assert!(config.validate().is_err());
+        config.boards.session_types.clear();
+        assert!(config.validate().is_err());
+
+        let mut config = Config {
+            results: vec![results_dir(&directory, None)],
+            upload: Some(UploadConfig {
+                token: "0123456789abcdef".to_string(),
+                directory: directory.clone(),
+                label: None,
+            }),
+            ..Default::default()
+        };
+        // Uploads can't go into a directory that's watched
+        assert!(config.validate().is_err());
+        config.results.clear();
+        assert!(config.validate().is_ok());
+        config.upload.as_mut().unwrap().token = "short".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config {
+            admin: Some(AdminConfig::default()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        config.admin.as_mut().unwrap().password = Some("1234567".to_string());
+        assert!(config.validate().is_err());
+        config.admin.as_mut().unwrap().password = Some("12345678".to_string());
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,96 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// How many events a slow SSE client can fall behind before it starts missing
+// them. Importing a whole directory of results files at once is the worst
+// case, but clients only need to know that something changed.
+const CHANNEL_CAPACITY: usize = 256;
+
+// Things that happened when a results file was added or an admin changed
+// something, in the order they're sent out. Serialized as-is for the SSE endpoint, with `type` doubling as
+// the SSE event name.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Session {
+        session_id: i64,
+        track: String,
+        session_type: String,
+        server_name: String,
+        server_label: Option<String>,
+        wet: bool,
+    },
+    // A session that was imported from a file that has changed since
+    SessionDeleted {
+        session_id: i64,
+    },
+    NewDriver {
+        steam_id: i64,
+        first_name: String,
+        last_name: String,
+        short_name: String,
+    },
+    // Names, nationalities, statuses and linked accounts that changed without
+    // any laps coming in, from an entry list or an admin
+    DriversChanged {
+        steam_ids: Vec<i64>,
+    },
+    // A lap an admin took off the boards, or put back
+    LapModerated {
+        steam_id: i64,
+        session_id: i64,
+        lap_id: i64,
+        track: String,
+        wet: bool,
+        laptime_ms: i64,
+        excluded: bool,
+    },
+    PersonalBest(LapEvent),
+    TrackRecord(LapEvent),
+    // Live timing laps, these don't get checked for records until the
+    // results file comes in.
+    ProvisionalLap {
+        steam_id: i64,
+        session_id: i64,
+        lap_id: i64,
+        track: String,
+        wet: bool,
+        laptime_ms: i64,
+        valid: bool,
+    },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LapEvent {
+    pub steam_id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub short_name: String,
+    pub session_id: i64,
+    pub lap_id: i64,
+    pub track: String,
+    pub wet: bool,
+    pub laptime_ms: i64,
+    pub previous_ms: Option<i64>,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Session { .. } => "session",
+            Self::SessionDeleted { .. } => "session_deleted",
+            Self::NewDriver { .. } => "new_driver",
+            Self::DriversChanged { .. } => "drivers_changed",
+            Self::PersonalBest(_) => "personal_best",
+            Self::TrackRecord(_) => "track_record",
+            Self::LapModerated { .. } => "lap_moderated",
+            Self::ProvisionalLap { .. } => "provisional_lap",
+        }
+    }
+}
+
+pub type EventSender = broadcast::Sender<Event>;
+
+pub fn channel() -> EventSender {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
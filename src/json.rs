@@ -39,13 +39,13 @@ pub struct Timing {
     //last_lap: Duration,
     //#[serde_as(as = "Vec<DurationMilliSeconds<f64>>")]
     //last_splits: Vec<Duration>,
-    //#[serde_as(as = "DurationMilliSeconds<u64>")]
-    //best_lap: Duration,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub best_lap: Duration,
     //#[serde_as(as = "Vec<DurationMilliSeconds<f64>>")]
     //best_splits: Vec<Duration>,
-    //#[serde_as(as = "DurationMilliSeconds<u64>")]
-    //total_time: Duration,
-    //lap_count: u64,
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub total_time: Duration,
+    pub lap_count: i64,
     //last_split_id: u64,
 }
 
@@ -54,12 +54,12 @@ pub struct Timing {
 #[serde(rename_all = "camelCase")]
 pub struct LeaderBoardLine {
     pub car: Car,
-    //current_driver: Driver,
+    pub current_driver: Driver,
     //current_driver_index: u64,
-    //timing: Timing,
-    //missing_mandatory_pitstop: i64,
-    //#[serde_as(as = "Vec<DurationMilliSeconds<f64>>")]
-    //driver_total_times: Vec<Duration>,
+    pub timing: Timing,
+    pub missing_mandatory_pitstop: i64,
+    #[serde_as(as = "Vec<DurationMilliSeconds<f64>>")]
+    pub driver_total_times: Vec<Duration>,
 }
 
 #[serde_as]
@@ -92,13 +92,13 @@ pub struct Lap {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Penalty {
-    //car_id: u64,
-    //driver_index: u64,
-    //reason: String,
-    //penalty: String,
-    //penalty_value: u64,
-    //violation_in_lap: i64,
-    //cleared_in_lap: i64,
+    pub car_id: i64,
+    pub driver_index: i64,
+    pub reason: String,
+    pub penalty: String,
+    pub penalty_value: i64,
+    pub violation_in_lap: i64,
+    pub cleared_in_lap: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -111,8 +111,8 @@ pub struct SessionResults {
     pub server_name: String,
     pub session_result: SessionResult,
     pub laps: Vec<Lap>,
-    //penalties: Vec<Penalty>,
-    //post_race_penalties: Option<Vec<Penalty>>,
+    pub penalties: Vec<Penalty>,
+    pub post_race_penalties: Option<Vec<Penalty>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -163,24 +163,25 @@ fn try_conversion(bytes: &[u8], encoding: Encoding) -> Result<String> {
 #[allow(clippy::cast_precision_loss)]
 pub fn bytes_to_json_string(bytes: &[u8]) -> Result<String> {
     // Check for BOM
-    if bytes[0..3] == [0xEF, 0xBB, 0xBF] {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
         // UTF-8 BOM
         return dedup_json(std::str::from_utf8(&bytes[3..])?);
-    } else if bytes[0..2] == [0xFF, 0xFE] {
+    } else if bytes.starts_with(&[0xFF, 0xFE]) {
         return dedup_json(&String::from_utf16le(&bytes[2..])?);
-    } else if bytes[0..2] == [0xFE, 0xFF] {
+    } else if bytes.starts_with(&[0xFE, 0xFF]) {
         return dedup_json(&String::from_utf16be(&bytes[2..])?);
     }
 
     // No BOM
     // Check for lots of BE NUL bytes
-    let (be_nuls, le_nuls) = bytes.chunks(2).fold(
+    // Uploaded files can be anything, including an odd number of bytes
+    let (be_nuls, le_nuls) = bytes.chunks_exact(2).fold(
         (0_usize, 0_usize),
         |(be_nuls, le_nuls), chunk| match chunk {
             [0, 0] => (be_nuls + 1, le_nuls + 1),
             [0, _] => (be_nuls + 1, le_nuls),
             [_, 0] => (be_nuls, le_nuls + 1),
-            _ => panic!("chunk size not 2?!"),
+            _ => (be_nuls, le_nuls),
         },
     );
     // Let's say if 45+% of the bytes are BE NULs, then it's probably UTF-16BE
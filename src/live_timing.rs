@@ -0,0 +1,434 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use sqlx::{Connection, SqliteConnection};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    time::{Duration, Instant},
+};
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::{
+    broadcasting::{self, EntryListCar, InboundMessage, LapInfo},
+    config,
+    events::{Event, EventSender},
+    personal_bests,
+};
+
+const DISPLAY_NAME: &str = "acc_hotlap_boards";
+const UPDATE_INTERVAL_MS: i32 = 250;
+// ACC stops sending when the server restarts, so re-register when it's quiet
+// for this long.
+const SILENCE_TIMEOUT: Duration = Duration::from_secs(10);
+// How long to wait before connecting again after an error
+const RETRY_DELAY_MIN: Duration = Duration::from_secs(1);
+const RETRY_DELAY_MAX: Duration = Duration::from_secs(300);
+
+// The broadcasting protocol only has car models, not the car groups that the
+// results files have.
+fn car_model_to_car_group(car_model: u8) -> &'static str {
+    match car_model {
+        9 | 28 => "CUP",
+        18 | 29 => "ST",
+        26 => "CHL",
+        27 => "TCX",
+        50..=61 => "GT4",
+        80..=86 => "GT2",
+        _ => "GT3",
+    }
+}
+
+// Results files use their own track IDs, the broadcasting protocol uses the
+// name as shown in game. Tracks that aren't in here get no live laps, rather
+// than laps on a board of their own.
+fn track_name_to_track_id(track_name: &str) -> Option<&'static str> {
+    let track_name = track_name.trim().to_lowercase().replace(['-', '_'], " ");
+    let track_id = match track_name.as_str() {
+        "barcelona" | "circuit de barcelona catalunya" => "barcelona",
+        "brands hatch" => "brands_hatch",
+        "circuit of the americas" | "cota" => "cota",
+        "donington" | "donington park" => "donington",
+        "hungaroring" => "hungaroring",
+        "imola" | "autodromo enzo e dino ferrari" => "imola",
+        "indianapolis" | "indianapolis motor speedway" => "indianapolis",
+        "kyalami" => "kyalami",
+        "laguna seca" | "weathertech raceway laguna seca" => "laguna_seca",
+        "misano" | "misano world circuit" => "misano",
+        "monza" | "autodromo nazionale monza" => "monza",
+        "mount panorama" | "bathurst" => "mount_panorama",
+        "nurburgring" => "nurburgring",
+        "nurburgring 24h" | "nordschleife" => "nurburgring_24h",
+        "oulton park" => "oulton_park",
+        "paul ricard" | "circuit paul ricard" => "paul_ricard",
+        "red bull ring" => "red_bull_ring",
+        "silverstone" => "silverstone",
+        "snetterton" | "snetterton 300" => "snetterton",
+        "spa" | "spa francorchamps" | "circuit de spa francorchamps" => "spa",
+        "suzuka" | "suzuka circuit" => "suzuka",
+        "valencia" | "circuit ricardo tormo" => "valencia",
+        "watkins glen" => "watkins_glen",
+        "zandvoort" | "circuit zandvoort" => "zandvoort",
+        "zolder" | "circuit zolder" => "zolder",
+        _ => return None,
+    };
+    Some(track_id)
+}
+
+struct LiveSession {
+    id: i64,
+    car_ids: HashMap<u16, i64>,
+}
+
+#[derive(Default)]
+struct LiveState {
+    connection_id: Option<i32>,
+    track: Option<String>,
+    session_key: Option<(u16, u8)>,
+    wet: bool,
+    cars: HashMap<u16, EntryListCar>,
+    laps_seen: HashMap<u16, u16>,
+    session: Option<LiveSession>,
+}
+
+struct Settings {
+    address: String,
+    password: String,
+    server_name: String,
+    record: Option<File>,
+    events: EventSender,
+}
+
+// Connects to the broadcasting interface of the ACC server and adds laps to
+// the database as they are completed. Only runs if live timing is configured.
+// Errors are logged and followed by a reconnect, waiting longer every time
+// they keep coming.
+pub async fn live_timing_task(events: EventSender) {
+    let Some(live_timing) = &config::get().live_timing else {
+        return;
+    };
+    let mut retry_delay = RETRY_DELAY_MIN;
+    loop {
+        let started = Instant::now();
+        if let Err(e) = run(live_timing, events.clone()).await {
+            error!(
+                "Live timing stopped: {:#}, reconnecting in {:?}",
+                e, retry_delay
+            );
+        }
+        // A connection that lasted a while starts over with a short delay
+        if started.elapsed() > RETRY_DELAY_MAX {
+            retry_delay = RETRY_DELAY_MIN;
+        }
+        tokio::time::sleep(retry_delay).await;
+        retry_delay = (retry_delay * 2).min(RETRY_DELAY_MAX);
+    }
+}
+
+async fn run(live_timing: &config::LiveTimingConfig, events: EventSender) -> Result<()> {
+    let config = config::get();
+    let mut settings = Settings {
+        address: live_timing.address.clone(),
+        password: live_timing.password.clone(),
+        server_name: live_timing.server_name.clone(),
+        record: live_timing
+            .record
+            .as_ref()
+            .map(|path| OpenOptions::new().create(true).append(true).open(path))
+            .transpose()?,
+        events,
+    };
+    let mut conn = SqliteConnection::connect(config.database_url()?).await?;
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(&settings.address).await?;
+    info!("Live timing connecting to {}", settings.address);
+
+    let mut state = LiveState::default();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        if state.connection_id.is_none() {
+            socket
+                .send(&broadcasting::register_request(
+                    DISPLAY_NAME,
+                    &settings.password,
+                    UPDATE_INTERVAL_MS,
+                    "",
+                ))
+                .await?;
+        }
+        let length = match timeout(SILENCE_TIMEOUT, socket.recv(&mut buffer)).await {
+            Ok(Ok(length)) => length,
+            // Nothing listening yet gives connection refused errors on some
+            // platforms, treat that the same as silence.
+            Ok(Err(e)) => {
+                debug!("Live timing receive error: {}", e);
+                tokio::time::sleep(SILENCE_TIMEOUT).await;
+                state = reset(&socket, state).await;
+                continue;
+            }
+            Err(_) => {
+                debug!("No live timing data for {:?}", SILENCE_TIMEOUT);
+                state = reset(&socket, state).await;
+                continue;
+            }
+        };
+        let packet = &buffer[..length];
+        if let Some(record) = settings.record.as_mut() {
+            record.write_all(&u32::try_from(length)?.to_le_bytes())?;
+            record.write_all(packet)?;
+        }
+        let message = match broadcasting::parse_message(packet) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to parse broadcasting packet: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_message(&socket, &mut conn, &settings, &mut state, message).await {
+            // Let the server know, so that it doesn't keep sending to a
+            // connection that's about to be replaced
+            reset(&socket, state).await;
+            return Err(e);
+        }
+    }
+}
+
+async fn reset(socket: &UdpSocket, state: LiveState) -> LiveState {
+    if let Some(connection_id) = state.connection_id {
+        // Best effort, the server might not be there anymore
+        let _ = socket
+            .send(&broadcasting::unregister_request(connection_id))
+            .await;
+    }
+    LiveState::default()
+}
+
+async fn handle_message(
+    socket: &UdpSocket,
+    conn: &mut SqliteConnection,
+    settings: &Settings,
+    state: &mut LiveState,
+    message: InboundMessage,
+) -> Result<()> {
+    match message {
+        InboundMessage::RegistrationResult {
+            connection_id,
+            success,
+            error_message,
+        } => {
+            if !success {
+                return Err(anyhow!(
+                    "Live timing registration failed: {}",
+                    error_message
+                ));
+            }
+            info!(
+                "Live timing registered with connection ID {}",
+                connection_id
+            );
+            state.connection_id = Some(connection_id);
+            socket
+                .send(&broadcasting::track_data_request(connection_id))
+                .await?;
+            socket
+                .send(&broadcasting::entry_list_request(connection_id))
+                .await?;
+        }
+        InboundMessage::TrackData { track_name } => {
+            let track = track_name_to_track_id(&track_name).map(str::to_string);
+            if track.is_none() {
+                warn!("Skipping live laps on unknown track {:?}", track_name);
+            }
+            if state.track != track {
+                debug!("Live timing track: {:?}", track);
+                state.track = track;
+                state.session = None;
+            }
+        }
+        InboundMessage::EntryList => state.cars.clear(),
+        InboundMessage::EntryListCar(car) => {
+            state.cars.insert(car.car_index, car);
+        }
+        InboundMessage::RealtimeUpdate(update) => {
+            let session_key = (update.session_index, update.session_type);
+            if state.session_key != Some(session_key) {
+                debug!("Live timing session change: {:?}", session_key);
+                state.session_key = Some(session_key);
+                state.session = None;
+                state.laps_seen.clear();
+            }
+            state.wet = update.wetness > 0;
+        }
+        InboundMessage::RealtimeCarUpdate(update) => {
+            let previous = state.laps_seen.insert(update.car_index, update.laps);
+            // The first update for a car only tells us where it's at, its last
+            // lap might have been driven before we connected.
+            if previous.is_some_and(|previous| update.laps > previous) {
+                add_provisional_lap(conn, settings, state, update.car_index, &update.last_lap)
+                    .await?;
+            }
+        }
+        InboundMessage::LapCompleted { car_index, message } => {
+            debug!("Lap completed by car {}: {}", car_index, message);
+        }
+        InboundMessage::Other(message_type) => {
+            debug!("Ignoring broadcasting message type {}", message_type);
+        }
+    }
+    Ok(())
+}
+
+async fn add_provisional_lap(
+    conn: &mut SqliteConnection,
+    settings: &Settings,
+    state: &mut LiveState,
+    car_index: u16,
+    lap: &LapInfo,
+) -> Result<()> {
+    let (Some(laptime_ms), Some(track), Some((_, session_type))) =
+        (lap.laptime_ms, state.track.as_ref(), state.session_key)
+    else {
+        return Ok(());
+    };
+    let splits = lap.splits.iter().copied().collect::<Option<Vec<_>>>();
+    let Some(splits) = splits.filter(|splits| !splits.is_empty()) else {
+        debug!("Skipping lap without splits for car {}", car_index);
+        return Ok(());
+    };
+    let Some(car) = state.cars.get(&car_index) else {
+        debug!("Skipping lap for unknown car {}", car_index);
+        return Ok(());
+    };
+    let Some(driver) = car.drivers.get(usize::from(lap.driver_index)) else {
+        return Ok(());
+    };
+    // There are no Steam IDs in the broadcasting protocol, so the driver
+    // needs to be known from an earlier results or entry list file. When more
+    // than one driver has the name there's no telling whose lap it is.
+    let steam_ids = sqlx::query!(
+        "SELECT steam_id FROM drivers
+        WHERE first_name = ? AND last_name = ? AND short_name = ?
+        LIMIT 2;",
+        driver.first_name,
+        driver.last_name,
+        driver.short_name
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let steam_id = match steam_ids.as_slice() {
+        [row] => row.steam_id,
+        [] => {
+            info!(
+                "Skipping live lap for unknown driver {} {}",
+                driver.first_name, driver.last_name
+            );
+            return Ok(());
+        }
+        _ => {
+            info!(
+                "Skipping live lap for {} {}, more than one driver has that name",
+                driver.first_name, driver.last_name
+            );
+            return Ok(());
+        }
+    };
+
+    let mut tx = conn.begin().await?;
+    // A results file for the session might have replaced it in the meantime
+    if let Some(session) = state.session.as_ref() {
+        let exists = sqlx::query!("SELECT id FROM sessions WHERE id = ?;", session.id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !exists {
+            state.session = None;
+        }
+    }
+    let session = match state.session.as_mut() {
+        Some(session) => session,
+        None => {
+            let timestamp = Utc::now().timestamp();
+            let session_type = broadcasting::session_type_to_results_type(session_type);
+            let id = sqlx::query!(
+                "INSERT INTO sessions (track, type, timestamp, server_name, wet) VALUES (?, ?, ?, ?, ?) RETURNING id;",
+                track,
+                session_type,
+                timestamp,
+                settings.server_name,
+                state.wet
+            )
+            .fetch_one(&mut *tx)
+            .await?
+            .id;
+            info!("Started live session {} on {}", id, track);
+            state.session.insert(LiveSession {
+                id,
+                car_ids: HashMap::new(),
+            })
+        }
+    };
+    let car_id = match session.car_ids.get(&car_index) {
+        Some(car_id) => *car_id,
+        None => {
+            let car_group = car_model_to_car_group(car.car_model);
+            let car_id = sqlx::query!(
+                "INSERT INTO cars (
+                        session_id, race_number, model, cup_category, car_group, team_name
+                    ) VALUES (?, ?, ?, ?, ?, ?)
+                    RETURNING id;",
+                session.id,
+                car.race_number,
+                car.car_model,
+                car.cup_category,
+                car_group,
+                car.team_name
+            )
+            .fetch_one(&mut *tx)
+            .await?
+            .id;
+            session.car_ids.insert(car_index, car_id);
+            car_id
+        }
+    };
+    let valid = lap.is_valid_for_best && !lap.is_invalid;
+    let lap_id = sqlx::query!(
+        "INSERT INTO laps (steam_id, session_id, car_id, time_ms, valid, provisional) VALUES (?, ?, ?, ?, ?, 1) RETURNING id;",
+        steam_id,
+        session.id,
+        car_id,
+        laptime_ms,
+        valid
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .id;
+    for (index, sector_time_ms) in splits.iter().enumerate() {
+        let sector = i64::try_from(index).unwrap() + 1;
+        sqlx::query!(
+            "INSERT INTO splits (lap_id, sector, time_ms) VALUES (?, ?, ?);",
+            lap_id,
+            sector,
+            sector_time_ms
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    personal_bests::refresh(&mut tx, track, Some(steam_id)).await?;
+    tx.commit().await?;
+    debug!(
+        "Provisional lap {} for {}: {}ms",
+        lap_id, steam_id, laptime_ms
+    );
+    // Nobody listening is fine
+    let _ = settings.events.send(Event::ProvisionalLap {
+        steam_id,
+        session_id: session.id,
+        lap_id,
+        track: track.clone(),
+        wet: state.wet,
+        laptime_ms: laptime_ms.into(),
+        valid,
+    });
+    Ok(())
+}
@@ -1,24 +1,115 @@
 #![feature(str_from_utf16_endian)]
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use async_watcher::{notify::RecursiveMode, AsyncDebouncer};
-use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use clap::{Parser, Subcommand};
 use itertools::Itertools;
-use log::{debug, info, warn};
-use serde::de::DeserializeOwned;
+use log::{debug, error, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::{Connection, Sqlite, SqliteConnection, SqliteExecutor, SqlitePool, Transaction};
 use std::{
     collections::{HashMap, HashSet},
-    env,
     fs::{self, read_dir, DirEntry},
-    path::Path,
-    time::Duration,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
 };
 
 mod appserver;
+mod broadcasting;
+mod config;
+mod events;
 mod json;
+mod live_timing;
+mod personal_bests;
+use events::{Event, EventSender, LapEvent};
 use json::bytes_to_json_string;
 
+// A directory results files are imported from. The label, if there is one, is
+// shown in place of the server name from the results files.
+#[derive(Clone, Debug)]
+struct ResultsDir {
+    path: PathBuf,
+    label: Option<String>,
+}
+
+impl ResultsDir {
+    fn new(path: impl AsRef<Path>, label: Option<String>) -> Result<Self> {
+        let path = path.as_ref();
+        // Canonical, so that files are recognized no matter how the directory
+        // was written down
+        let path = fs::canonicalize(path)
+            .with_context(|| format!("Results directory {} not found", path.display()))?;
+        Ok(Self { path, label })
+    }
+
+    fn key(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+}
+
+// What became of a file that was looked at. Only the upload endpoint shows
+// this to anyone, everything else goes by the logs.
+#[derive(Debug, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+enum FileOutcome {
+    // Not named like a results or entrylist file
+    Ignored,
+    AlreadyKnown,
+    Unreadable { error: String },
+    // Couldn't be read before, and hasn't changed since
+    Quarantined,
+    // Its session was deleted by an admin
+    Deleted,
+    EmptySession,
+    DuplicateSession,
+    Session(SessionSummary),
+    Entrylist { drivers: usize },
+}
+
+#[derive(Debug, Serialize)]
+struct SessionSummary {
+    session_id: i64,
+    track: String,
+    session_type: String,
+    server_name: String,
+    wet: bool,
+    cars: usize,
+    drivers: usize,
+    laps: usize,
+    new_drivers: usize,
+    personal_bests: usize,
+    track_records: usize,
+    // When the file was imported before and has changed since
+    replaced_session_id: Option<i64>,
+}
+
+// The configured results directories. There has to be at least one, unless
+// results files get uploaded instead.
+fn results_dirs() -> Result<Vec<ResultsDir>> {
+    let config = config::get();
+    if config.results.is_empty() && config.upload.is_none() {
+        bail!(
+            "No results directories configured, set `results` in the config file or RESULTS_PATH"
+        );
+    }
+    config
+        .results
+        .iter()
+        .map(|results_dir| ResultsDir::new(&results_dir.path, results_dir.label.clone()))
+        .collect()
+}
+
+// Where files that come in through the upload endpoint are kept
+fn upload_dir() -> Result<Option<ResultsDir>> {
+    config::get()
+        .upload
+        .as_ref()
+        .map(|upload| ResultsDir::new(&upload.directory, upload.label.clone()))
+        .transpose()
+}
+
 fn read_file<T>(path: impl AsRef<Path>) -> Result<T>
 where
     T: DeserializeOwned,
@@ -26,19 +117,77 @@ where
     let path = path.as_ref();
     // Read file into Vec<u8>
     let bytes = fs::read(path)?;
+    parse_file(&bytes, &path.file_name().unwrap().to_string_lossy())
+}
+
+fn parse_file<T>(bytes: &[u8], filename: &str) -> Result<T>
+where
+    T: DeserializeOwned,
+{
     // Convert UTF-16 to UTF-8
-    let json_text = bytes_to_json_string(&bytes).context("Invalid JSON structure")?;
+    let json_text = bytes_to_json_string(bytes).context("Invalid JSON structure")?;
     // Parse JSON
-    let result: T = serde_json::from_str(&json_text).with_context(|| {
-        format!(
-            "Failed to parse JSON file {}\n{}",
-            path.file_name().unwrap().to_string_lossy(),
-            json_text
-        )
-    })?;
+    let result: T = serde_json::from_str(&json_text)
+        .with_context(|| format!("Failed to parse JSON file {filename}"))?;
     Ok(result)
 }
 
+// Files are remembered by their contents, so that a changed one gets imported
+// again, and one that can't be parsed only gets another go when it changes or
+// when an admin retries it.
+fn file_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+// What a file looked like when it was imported, to tell when it's been written
+// again. Size and modification time are cheap to check, and when they're the
+// same the file isn't read at all. When they're not, the hash decides.
+struct FileStamp {
+    size: i64,
+    mtime: Option<i64>,
+    hash: String,
+}
+
+impl FileStamp {
+    fn new(metadata: &fs::Metadata, bytes: &[u8]) -> Self {
+        Self {
+            size: file_size(metadata),
+            mtime: file_mtime(metadata),
+            hash: file_hash(bytes),
+        }
+    }
+}
+
+fn file_size(metadata: &fs::Metadata) -> i64 {
+    i64::try_from(metadata.len()).unwrap_or(i64::MAX)
+}
+
+fn file_mtime(metadata: &fs::Metadata) -> Option<i64> {
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    i64::try_from(mtime.as_nanos()).ok()
+}
+
+// The results directories and the upload directory, whichever there are
+fn configured_dirs() -> Result<Vec<ResultsDir>> {
+    let mut configured = if config::get().results.is_empty() {
+        Vec::new()
+    } else {
+        results_dirs()?
+    };
+    configured.extend(upload_dir()?);
+    Ok(configured)
+}
+
+// The configured results directory that a file is in, or an unlabeled one for
+// files from anywhere else
+fn results_dir_for(directory: &Path) -> Result<ResultsDir> {
+    let directory = ResultsDir::new(directory, None)?;
+    Ok(configured_dirs()?
+        .into_iter()
+        .find(|results_dir| results_dir.path == directory.path)
+        .unwrap_or(directory))
+}
+
 async fn delete_session(executor: impl SqliteExecutor<'_>, session_id: i64) -> Result<()> {
     sqlx::query!("DELETE FROM sessions WHERE id = ?;", session_id)
         .execute(executor)
@@ -46,6 +195,33 @@ async fn delete_session(executor: impl SqliteExecutor<'_>, session_id: i64) -> R
     Ok(())
 }
 
+async fn delete_provisional_sessions(
+    tx: &mut Transaction<'_, Sqlite>,
+    session_results: &json::SessionResults,
+    timestamp: DateTime<Utc>,
+) -> Result<()> {
+    let timestamp = timestamp.timestamp();
+    let deleted = sqlx::query!(
+        "DELETE FROM sessions
+        WHERE track = ?
+        AND type = ?
+        AND server_name = ?
+        AND timestamp <= ?
+        AND id IN (SELECT session_id FROM laps WHERE provisional = 1);",
+        session_results.track_name,
+        session_results.session_type,
+        session_results.server_name,
+        timestamp
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if deleted > 0 {
+        info!("Replaced {} provisional session(s)", deleted);
+    }
+    Ok(())
+}
+
 async fn check_previous_session_overlap(
     tx: &mut Transaction<'_, Sqlite>,
     session_results: &json::SessionResults,
@@ -127,27 +303,78 @@ async fn add_session_results(
     conn: &mut SqliteConnection,
     session_results: json::SessionResults,
     filename: &str,
-) -> Result<()> {
+    results_dir: &ResultsDir,
+    stamp: &FileStamp,
+    events: &EventSender,
+) -> Result<FileOutcome> {
     let mut tx = sqlx::Connection::begin(&mut *conn).await?;
     let timestamp = filename_to_timestamp(filename)?;
+    let timestamp_secs = timestamp.timestamp();
+
+    // What the file has now takes the place of what it had before
+    let replaced_session = delete_file_session(
+        filename,
+        results_dir,
+        timestamp_secs,
+        &session_results.server_name,
+        &mut tx,
+    )
+    .await?;
+    let replaced_session_id = replaced_session.as_ref().map(|session| session.id);
 
     if session_results.laps.is_empty() {
         info!("Skipping empty session: {}", filename);
-        register_file(filename, &mut tx).await?;
+        register_file(filename, results_dir, stamp, None, &mut tx).await?;
         tx.commit().await?;
-        return Ok(());
+        send_session_deleted(events, replaced_session_id);
+        return Ok(FileOutcome::EmptySession);
+    }
+
+    // The same session can end up in more than one results directory
+    let duplicate = sqlx::query!(
+        "SELECT id FROM sessions WHERE timestamp = ? AND server_name = ?;",
+        timestamp_secs,
+        session_results.server_name
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some();
+    if duplicate {
+        warn!("Skipping session that is already in database: {}", filename);
+        register_file(filename, results_dir, stamp, None, &mut tx).await?;
+        tx.commit().await?;
+        send_session_deleted(events, replaced_session_id);
+        return Ok(FileOutcome::DuplicateSession);
     }
 
     // Check for and delete previous session if current one is a superset of them
     while check_previous_session_overlap(&mut tx, &session_results, timestamp).await? {}
 
-    let session_id = insert_session_row(timestamp, &session_results, &mut tx).await?;
+    // The results file replaces whatever live timing picked up of this session
+    delete_provisional_sessions(&mut tx, &session_results, timestamp).await?;
+
+    let session_id = insert_session_row(
+        timestamp,
+        &session_results,
+        results_dir.label.as_deref(),
+        &mut tx,
+    )
+    .await?;
 
     // Snag all of the driver info from the session results
     let mut steam_id_to_driver_names = HashMap::new();
     let mut car_driver_to_steam_id = HashMap::new();
     let mut car_id_to_db_id = HashMap::new();
-    for line in session_results.session_result.leader_board_lines {
+    let mut classified_lines = Vec::new();
+    // Practice has no classification to speak of, the order of the
+    // leaderboard lines is only meaningful for qualifying and races.
+    let classified = matches!(session_results.session_type.chars().next(), Some('Q' | 'R'));
+    for (index, line) in session_results
+        .session_result
+        .leader_board_lines
+        .into_iter()
+        .enumerate()
+    {
         // there's a driver index in the lap data that matches the index from
         // enumerate()
         for (index, driver) in line.car.drivers.iter().enumerate() {
@@ -159,11 +386,70 @@ async fn add_session_results(
         }
         let db_car_id = insert_car(session_id, &line, &mut tx).await?;
         car_id_to_db_id.insert(line.car.car_id, db_car_id);
+        if classified {
+            classified_lines.push((db_car_id, i64::try_from(index).unwrap() + 1, line));
+        }
     }
 
-    upsert_driver_data(steam_id_to_driver_names, &mut tx).await?;
+    let mut summary = SessionSummary {
+        session_id,
+        track: session_results.track_name.clone(),
+        session_type: session_results.session_type.clone(),
+        server_name: session_results.server_name.clone(),
+        wet: session_results.session_result.is_wet_session != 0,
+        cars: car_id_to_db_id.len(),
+        drivers: steam_id_to_driver_names.len(),
+        laps: session_results.laps.len(),
+        new_drivers: 0,
+        personal_bests: 0,
+        track_records: 0,
+        replaced_session_id,
+    };
+    let mut session_events = replaced_session_id
+        .map(|session_id| Event::SessionDeleted { session_id })
+        .into_iter()
+        .collect::<Vec<_>>();
+    session_events.push(Event::Session {
+        session_id,
+        track: session_results.track_name.clone(),
+        session_type: session_results.session_type.clone(),
+        server_name: session_results.server_name.clone(),
+        server_label: results_dir.label.clone(),
+        wet: session_results.session_result.is_wet_session != 0,
+    });
+    for (steam_id, driver) in &steam_id_to_driver_names {
+        let known = sqlx::query!("SELECT steam_id FROM drivers WHERE steam_id = ?;", steam_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .is_some();
+        if !known {
+            session_events.push(Event::NewDriver {
+                steam_id: *steam_id,
+                first_name: driver.first_name.clone(),
+                last_name: driver.last_name.clone(),
+                short_name: driver.short_name.clone(),
+            });
+        }
+    }
+
+    upsert_driver_data(steam_id_to_driver_names, timestamp_secs, &mut tx).await?;
+
+    for (db_car_id, position, line) in classified_lines {
+        insert_classification(db_car_id, position, &line, &mut tx).await?;
+    }
 
+    // Laps are numbered per car, starting at 1, same as the penalties do
+    let penalty_laps = session_results
+        .penalties
+        .iter()
+        .filter(|penalty| penalty.cleared_in_lap > 0)
+        .map(|penalty| (penalty.car_id, penalty.cleared_in_lap))
+        .collect::<HashSet<_>>();
+    let mut car_lap_counts: HashMap<i64, i64> = HashMap::new();
     for lap in session_results.laps {
+        let lap_number = car_lap_counts.entry(lap.car_id).or_default();
+        *lap_number += 1;
+        let penalty_served = penalty_laps.contains(&(lap.car_id, *lap_number));
         let steam_id = car_driver_to_steam_id
             .get(&(lap.car_id, lap.driver_index))
             .ok_or_else(|| {
@@ -178,12 +464,14 @@ async fn add_session_results(
             .ok_or_else(|| anyhow!("No car ID found for car {}", lap.car_id))?;
         let laptime_ms: i64 = lap.laptime.as_millis().try_into().unwrap();
         let lap_id = sqlx::query!(
-            "INSERT INTO laps (steam_id, session_id, car_id, time_ms, valid) VALUES (?, ?, ?, ?, ?) RETURNING id;",
+            "INSERT INTO laps (steam_id, session_id, car_id, time_ms, valid, penalty_served, lap_number) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id;",
             steam_id,
             session_id,
             car_id,
             laptime_ms,
-            lap.is_valid_for_best
+            lap.is_valid_for_best,
+            penalty_served,
+            *lap_number
         )
         .fetch_one(&mut *tx)
         .await?.id;
@@ -202,42 +490,507 @@ async fn add_session_results(
         }
     }
 
-    register_file(filename, &mut tx).await?;
+    let post_race_penalties = session_results.post_race_penalties.unwrap_or_default();
+    for (penalty, post_race) in session_results
+        .penalties
+        .iter()
+        .map(|penalty| (penalty, false))
+        .chain(post_race_penalties.iter().map(|penalty| (penalty, true)))
+    {
+        let car_id = car_id_to_db_id
+            .get(&penalty.car_id)
+            .ok_or_else(|| anyhow!("No car ID found for car {}", penalty.car_id))?;
+        let steam_id = car_driver_to_steam_id.get(&(penalty.car_id, penalty.driver_index));
+        insert_penalty(session_id, *car_id, steam_id, penalty, post_race, &mut tx).await?;
+    }
+
+    // Laps that moderators excluded before the file was imported again
+    sqlx::query!(
+        "UPDATE laps SET excluded = 1
+        WHERE session_id = ?1
+        AND EXISTS (
+            SELECT 1
+            FROM lap_exclusions e
+            WHERE e.session_timestamp = ?2
+            AND e.server_name = ?3
+            AND e.steam_id = laps.steam_id
+            AND e.lap_number = laps.lap_number
+        );",
+        session_id,
+        timestamp_secs,
+        session_results.server_name
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Also takes care of whatever the overlap check and the provisional
+    // sessions left behind, they're always on the same track.
+    personal_bests::refresh(&mut tx, &session_results.track_name, None).await?;
+
+    session_events.extend(
+        get_lap_events(
+            session_id,
+            &session_results.track_name,
+            session_results.session_result.is_wet_session != 0,
+            replaced_session.as_ref(),
+            &mut tx,
+        )
+        .await?,
+    );
+
+    register_file(filename, results_dir, stamp, Some(session_id), &mut tx).await?;
     tx.commit().await?;
+
+    for event in session_events {
+        match event {
+            Event::NewDriver { .. } => summary.new_drivers += 1,
+            Event::PersonalBest(_) => summary.personal_bests += 1,
+            Event::TrackRecord(_) => summary.track_records += 1,
+            _ => {}
+        }
+        // Nobody listening is fine
+        let _ = events.send(event);
+    }
+    Ok(FileOutcome::Session(summary))
+}
+
+// Compares the best lap of each driver in the session against what was in
+// the database before it, for the same track and conditions. That includes
+// the earlier version of the session's file, if it had one, so that a file
+// being rewritten mid-session doesn't announce the same laps again.
+async fn get_lap_events(
+    session_id: i64,
+    track: &str,
+    wet: bool,
+    replaced_session: Option<&ReplacedSession>,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<Vec<Event>, anyhow::Error> {
+    let best_laps = sqlx::query!(
+        r#"
+        SELECT l.id AS lap_id,
+            p.steam_id,
+            MIN(l.time_ms) AS "laptime_ms!: i64",
+            p.first_name,
+            p.last_name,
+            p.short_name,
+            (SELECT MIN(pl.time_ms)
+             FROM laps pl
+             INNER JOIN sessions ps ON pl.session_id = ps.id
+             INNER JOIN drivers pa ON pl.steam_id = pa.steam_id
+             WHERE ps.track = ?2 AND ps.wet = ?3
+             AND COALESCE(pa.merged_into, pa.steam_id) = p.steam_id
+             AND pl.valid = 1 AND pl.excluded = 0 AND pl.session_id != ?1
+            ) AS "previous_ms?: i64"
+        FROM laps l
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE l.session_id = ?1 AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        GROUP BY p.steam_id
+        ORDER BY MIN(l.time_ms);
+        "#,
+        session_id,
+        track,
+        wet
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let previous_record_ms = sqlx::query!(
+        r#"
+        SELECT MIN(l.time_ms) AS "time_ms?: i64"
+        FROM laps l
+        INNER JOIN sessions s ON l.session_id = s.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE s.track = ?2 AND s.wet = ?3
+        AND l.valid = 1 AND l.excluded = 0 AND l.session_id != ?1
+        AND p.status = 'active';
+        "#,
+        session_id,
+        track,
+        wet
+    )
+    .fetch_one(&mut **tx)
+    .await?
+    .time_ms;
+    let replaced_laps = replaced_session
+        .filter(|replaced| replaced.track == track && replaced.wet == wet)
+        .map(|replaced| &replaced.best_laps);
+    let previous_record_ms = previous_record_ms
+        .into_iter()
+        .chain(replaced_laps.and_then(|laps| laps.values().min().copied()))
+        .min();
+
+    let mut events = Vec::new();
+    for (index, mut row) in best_laps.into_iter().enumerate() {
+        row.previous_ms = row
+            .previous_ms
+            .into_iter()
+            .chain(replaced_laps.and_then(|laps| laps.get(&row.steam_id).copied()))
+            .min();
+        if row
+            .previous_ms
+            .is_some_and(|previous_ms| row.laptime_ms >= previous_ms)
+        {
+            continue;
+        }
+        let lap_event = LapEvent {
+            steam_id: row.steam_id,
+            first_name: row.first_name,
+            last_name: row.last_name,
+            short_name: row.short_name,
+            session_id,
+            lap_id: row.lap_id,
+            track: track.to_string(),
+            wet,
+            laptime_ms: row.laptime_ms,
+            previous_ms: row.previous_ms,
+        };
+        // Only the fastest lap of the session can be a new record
+        if index == 0 && previous_record_ms.is_none_or(|record_ms| row.laptime_ms < record_ms) {
+            events.push(Event::TrackRecord(LapEvent {
+                previous_ms: previous_record_ms,
+                ..lap_event.clone()
+            }));
+        }
+        events.push(Event::PersonalBest(lap_event));
+    }
+    Ok(events)
+}
+
+// The session imported from an earlier version of a file. Its best laps were
+// announced back then, they're no news when the file changes.
+struct ReplacedSession {
+    id: i64,
+    track: String,
+    wet: bool,
+    // Best valid lap of each main account that's on the boards
+    best_laps: HashMap<i64, i64>,
+}
+
+// Deletes the session imported from an earlier version of a file, if there
+// is one
+async fn delete_file_session(
+    filename: &str,
+    results_dir: &ResultsDir,
+    timestamp_secs: i64,
+    server_name: &str,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<Option<ReplacedSession>> {
+    let directory = results_dir.key();
+    // Files imported before the session was kept with them have theirs found
+    // by when and where it was, as long as no other file or live timing has it
+    let Some(session) = sqlx::query!(
+        r#"SELECT s.id, s.track, s.wet AS "wet: bool"
+        FROM known_files k
+        INNER JOIN sessions s ON k.session_id = s.id
+        WHERE k.directory = ?1 AND k.path = ?2
+        UNION ALL
+        SELECT s.id, s.track, s.wet AS "wet: bool"
+        FROM known_files k
+        INNER JOIN sessions s ON s.timestamp = ?3 AND s.server_name = ?4
+        WHERE k.directory IN (?1, '') AND k.path = ?2
+        AND k.session_id IS NULL AND k.hash IS NULL
+        AND s.id NOT IN (SELECT session_id FROM known_files WHERE session_id IS NOT NULL)
+        AND s.id NOT IN (SELECT session_id FROM laps WHERE provisional = 1)
+        LIMIT 1;"#,
+        directory,
+        filename,
+        timestamp_secs,
+        server_name
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let best_laps = sqlx::query!(
+        r#"
+        SELECT p.steam_id AS "steam_id!: i64", MIN(l.time_ms) AS "laptime_ms!: i64"
+        FROM laps l
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers p ON COALESCE(a.merged_into, a.steam_id) = p.steam_id
+        WHERE l.session_id = ? AND l.valid = 1 AND l.excluded = 0
+        AND p.status = 'active'
+        GROUP BY p.steam_id;
+        "#,
+        session.id
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|row| (row.steam_id, row.laptime_ms))
+    .collect();
+    info!("{} changed, replacing session {}", filename, session.id);
+    delete_session(&mut **tx, session.id).await?;
+    // The new version might not have laps on the same track, or any laps
+    personal_bests::refresh(tx, &session.track, None).await?;
+    Ok(Some(ReplacedSession {
+        id: session.id,
+        track: session.track,
+        wet: session.wet,
+        best_laps,
+    }))
+}
+
+fn send_session_deleted(events: &EventSender, session_id: Option<i64>) {
+    if let Some(session_id) = session_id {
+        // Nobody listening is fine
+        let _ = events.send(Event::SessionDeleted { session_id });
+    }
+}
+
+async fn register_file(
+    filename: &str,
+    results_dir: &ResultsDir,
+    stamp: &FileStamp,
+    session_id: Option<i64>,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<()> {
+    let directory = results_dir.key();
+    // A file known from before directories were tracked is now known in this
+    // one only
+    sqlx::query!(
+        "DELETE FROM known_files WHERE directory IN (?, '') AND path = ?;",
+        directory,
+        filename
+    )
+    .execute(&mut **tx)
+    .await?;
+    let now = Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO known_files (directory, path, size, mtime, hash, session_id, imported_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?);",
+        directory,
+        filename,
+        stamp.size,
+        stamp.mtime,
+        stamp.hash,
+        session_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await?;
+    // It made it in after all
+    sqlx::query!(
+        "DELETE FROM quarantined_files WHERE directory = ? AND path = ?;",
+        directory,
+        filename
+    )
+    .execute(&mut **tx)
+    .await?;
     Ok(())
 }
 
-async fn register_file(filename: &str, tx: &mut Transaction<'_, Sqlite>) -> Result<()> {
-    sqlx::query!("INSERT INTO known_files (path) VALUES (?);", filename)
-        .execute(&mut **tx)
-        .await?;
+async fn quarantine_file(
+    filename: &str,
+    results_dir: &ResultsDir,
+    hash: &str,
+    error: &anyhow::Error,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    let directory = results_dir.key();
+    let error = format!("{error:?}");
+    let now = Utc::now().timestamp();
+    sqlx::query!(
+        "INSERT INTO quarantined_files (directory, path, hash, error, first_seen, last_seen)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+        ON CONFLICT (directory, path) DO UPDATE SET
+        hash = excluded.hash,
+        error = excluded.error,
+        last_seen = excluded.last_seen,
+        attempts = attempts + 1;",
+        directory,
+        filename,
+        hash,
+        error,
+        now
+    )
+    .execute(&mut *conn)
+    .await?;
     Ok(())
 }
 
 async fn upsert_driver_data(
     steam_id_to_driver_names: HashMap<i64, json::Driver>,
+    seen: i64,
     tx: &mut Transaction<'_, Sqlite>,
 ) -> Result<(), anyhow::Error> {
     for (id, driver) in steam_id_to_driver_names {
-        sqlx::query!(
-            "INSERT INTO drivers
-            (steam_id, first_name, last_name, short_name)
-            VALUES (?, ?, ?, ?)
-            ON CONFLICT (steam_id) DO UPDATE SET
-            first_name = excluded.first_name,
-            last_name = excluded.last_name,
-            short_name = excluded.short_name;",
+        record_driver_name(
             id,
-            driver.first_name,
-            driver.last_name,
-            driver.short_name
+            &driver.first_name,
+            &driver.last_name,
+            &driver.short_name,
+            "results",
+            seen,
+            tx,
         )
-        .execute(&mut **tx)
         .await?;
     }
     Ok(())
 }
 
+// Keeps the names a driver went by, with when and where they were seen. The
+// drivers table gets the most recently seen one, which isn't necessarily the
+// one from the last file imported, entry lists in particular can be old.
+async fn record_driver_name(
+    steam_id: i64,
+    first_name: &str,
+    last_name: &str,
+    short_name: &str,
+    source: &str,
+    seen: i64,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "INSERT INTO drivers
+        (steam_id, first_name, last_name, short_name)
+        VALUES (?, ?, ?, ?)
+        ON CONFLICT (steam_id) DO NOTHING;",
+        steam_id,
+        first_name,
+        last_name,
+        short_name
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO driver_names
+        (steam_id, first_name, last_name, short_name, source, first_seen, last_seen)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+        ON CONFLICT (steam_id, first_name, last_name, short_name, source) DO UPDATE SET
+        first_seen = MIN(first_seen, excluded.first_seen),
+        last_seen = MAX(last_seen, excluded.last_seen);",
+        steam_id,
+        first_name,
+        last_name,
+        short_name,
+        source,
+        seen
+    )
+    .execute(&mut **tx)
+    .await?;
+    // Results files win from entry lists of the same session, they have the
+    // names that were actually used
+    sqlx::query!(
+        "UPDATE drivers SET
+            (first_name, last_name, short_name) = (
+                SELECT first_name, last_name, short_name
+                FROM driver_names
+                WHERE steam_id = ?1
+                ORDER BY last_seen DESC, source = 'results' DESC
+                LIMIT 1
+            )
+        WHERE steam_id = ?1 AND locked = 0;",
+        steam_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// Linked Steam accounts count as the account they're linked to
+async fn main_account(conn: &mut SqliteConnection, steam_id: i64) -> Result<i64> {
+    Ok(sqlx::query!(
+        r#"SELECT COALESCE(merged_into, steam_id) AS "steam_id!: i64"
+        FROM drivers
+        WHERE steam_id = ?;"#,
+        steam_id
+    )
+    .fetch_optional(conn)
+    .await?
+    .map_or(steam_id, |row| row.steam_id))
+}
+
+// Links a second Steam account of a driver to their main one, along with any
+// accounts that were linked to it. Returns false if either isn't known, if the
+// main account is linked to another one itself, or if they have different
+// statuses. Only the main account's status counts for the boards, so a banned
+// account would be back on them under the main account's name otherwise.
+async fn link_driver(
+    steam_id: i64,
+    main_steam_id: i64,
+    conn: &mut SqliteConnection,
+) -> Result<bool> {
+    if steam_id == main_steam_id {
+        return Ok(false);
+    }
+    let mut tx = conn.begin().await?;
+    let Some(main) = sqlx::query!(
+        "SELECT merged_into, status FROM drivers WHERE steam_id = ?;",
+        main_steam_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(false);
+    };
+    if main.merged_into.is_some() {
+        return Ok(false);
+    }
+    let same_status = sqlx::query!("SELECT status FROM drivers WHERE steam_id = ?;", steam_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .is_some_and(|row| row.status == main.status);
+    if !same_status {
+        return Ok(false);
+    }
+    let previous_main = main_account(&mut tx, steam_id).await?;
+    let linked = sqlx::query!(
+        "UPDATE drivers SET merged_into = ?1 WHERE steam_id = ?2 OR merged_into = ?2;",
+        main_steam_id,
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if linked == 0 {
+        return Ok(false);
+    }
+    // Personal bests of the account are the main account's now
+    sqlx::query!(
+        "DELETE FROM personal_best_laps WHERE steam_id = ?;",
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM personal_best_sectors WHERE steam_id = ?;",
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    personal_bests::refresh_driver(&mut tx, main_steam_id).await?;
+    if previous_main != steam_id {
+        personal_bests::refresh_driver(&mut tx, previous_main).await?;
+    }
+    tx.commit().await?;
+    info!("Linked driver {} to {}", steam_id, main_steam_id);
+    Ok(true)
+}
+
+// Makes a linked Steam account a driver of its own again. Returns false if it
+// wasn't linked.
+async fn unlink_driver(steam_id: i64, conn: &mut SqliteConnection) -> Result<bool> {
+    let mut tx = conn.begin().await?;
+    let main_steam_id = main_account(&mut tx, steam_id).await?;
+    if main_steam_id == steam_id {
+        return Ok(false);
+    }
+    sqlx::query!(
+        "UPDATE drivers SET merged_into = NULL WHERE steam_id = ?;",
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    personal_bests::refresh_driver(&mut tx, main_steam_id).await?;
+    personal_bests::refresh_driver(&mut tx, steam_id).await?;
+    tx.commit().await?;
+    info!("Unlinked driver {} from {}", steam_id, main_steam_id);
+    Ok(true)
+}
+
 async fn insert_car(
     session_id: i64,
     line: &json::LeaderBoardLine,
@@ -261,18 +1014,105 @@ async fn insert_car(
     .id)
 }
 
+// ACC uses i32::MAX for times it doesn't have, like the best lap of a car
+// that never set a valid one.
+fn acc_time_to_ms(time: Duration) -> Option<i64> {
+    let ms = i64::try_from(time.as_millis()).unwrap();
+    (ms < i64::from(i32::MAX)).then_some(ms)
+}
+
+async fn insert_classification(
+    db_car_id: i64,
+    position: i64,
+    line: &json::LeaderBoardLine,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<(), anyhow::Error> {
+    let total_time_ms = acc_time_to_ms(line.timing.total_time);
+    let best_lap_ms = acc_time_to_ms(line.timing.best_lap);
+    sqlx::query!(
+        "UPDATE cars SET
+            position = ?,
+            lap_count = ?,
+            total_time_ms = ?,
+            best_lap_ms = ?,
+            missing_mandatory_pitstop = ?,
+            finishing_steam_id = ?
+        WHERE id = ?;",
+        position,
+        line.timing.lap_count,
+        total_time_ms,
+        best_lap_ms,
+        line.missing_mandatory_pitstop,
+        line.current_driver.steam_id,
+        db_car_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    for (index, (driver, total_time)) in line
+        .car
+        .drivers
+        .iter()
+        .zip(&line.driver_total_times)
+        .enumerate()
+    {
+        let driver_index = i64::try_from(index).unwrap();
+        let total_time_ms = acc_time_to_ms(*total_time).unwrap_or(0);
+        sqlx::query!(
+            "INSERT INTO car_drivers (car_id, driver_index, steam_id, total_time_ms)
+            VALUES (?, ?, ?, ?);",
+            db_car_id,
+            driver_index,
+            driver.steam_id,
+            total_time_ms
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+async fn insert_penalty(
+    session_id: i64,
+    car_id: i64,
+    steam_id: Option<&i64>,
+    penalty: &json::Penalty,
+    post_race: bool,
+    tx: &mut Transaction<'_, Sqlite>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "INSERT INTO penalties (
+                session_id, car_id, steam_id, reason, penalty, penalty_value,
+                violation_in_lap, cleared_in_lap, post_race
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        session_id,
+        car_id,
+        steam_id,
+        penalty.reason,
+        penalty.penalty,
+        penalty.penalty_value,
+        penalty.violation_in_lap,
+        penalty.cleared_in_lap,
+        post_race
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
 async fn insert_session_row(
     timestamp: DateTime<Utc>,
     session_results: &json::SessionResults,
+    server_label: Option<&str>,
     tx: &mut Transaction<'_, Sqlite>,
 ) -> Result<i64, anyhow::Error> {
     let timestamp = timestamp.timestamp();
     Ok(sqlx::query!(
-        "INSERT INTO sessions (track, type, timestamp, server_name, wet) VALUES (?, ?, ?, ?, ?) RETURNING id;",
+        "INSERT INTO sessions (track, type, timestamp, server_name, server_label, wet) VALUES (?, ?, ?, ?, ?, ?) RETURNING id;",
         session_results.track_name,
         session_results.session_type,
         timestamp,
         session_results.server_name,
+        server_label,
         session_results.session_result.is_wet_session
     )
     .fetch_one(&mut **tx)
@@ -283,28 +1123,31 @@ async fn add_entrylist(
     conn: &mut SqliteConnection,
     entrylist: json::EntryList,
     filename: &str,
-) -> Result<()> {
+    results_dir: &ResultsDir,
+    stamp: &FileStamp,
+    events: &EventSender,
+) -> Result<FileOutcome> {
+    let seen = filename_to_timestamp(filename)?.timestamp();
     let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+    let mut drivers = 0;
+    let mut steam_ids = Vec::new();
     for entry in entrylist.entries {
         for driver in entry.drivers {
-            sqlx::query!(
-                "INSERT INTO drivers 
-                (steam_id, first_name, last_name, short_name)
-                VALUES (?, ?, ?, ?)
-                ON CONFLICT(steam_id) DO UPDATE SET
-                first_name = excluded.first_name,
-                last_name = excluded.last_name,
-                short_name = excluded.short_name;",
+            drivers += 1;
+            steam_ids.push(driver.steam_id);
+            record_driver_name(
                 driver.steam_id,
-                driver.first_name,
-                driver.last_name,
-                driver.short_name,
+                &driver.first_name,
+                &driver.last_name,
+                &driver.short_name,
+                "entrylist",
+                seen,
+                &mut tx,
             )
-            .execute(&mut *tx)
             .await?;
             if let Some(nickname) = driver.nick_name {
                 sqlx::query!(
-                    "UPDATE drivers SET nickname = ? WHERE steam_id = ?;",
+                    "UPDATE drivers SET nickname = ? WHERE steam_id = ? AND locked = 0;",
                     nickname,
                     driver.steam_id
                 )
@@ -313,7 +1156,7 @@ async fn add_entrylist(
             }
             if let Some(nationality) = driver.nationality {
                 sqlx::query!(
-                    "UPDATE drivers SET nationality = ? WHERE steam_id = ?;",
+                    "UPDATE drivers SET nationality = ? WHERE steam_id = ? AND locked = 0;",
                     nationality,
                     driver.steam_id
                 )
@@ -322,133 +1165,1431 @@ async fn add_entrylist(
             }
         }
     }
-    register_file(filename, &mut tx).await?;
+    register_file(filename, results_dir, stamp, None, &mut tx).await?;
     tx.commit().await?;
-    Ok(())
+    steam_ids.sort_unstable();
+    steam_ids.dedup();
+    // Nobody listening is fine
+    let _ = events.send(Event::DriversChanged { steam_ids });
+    Ok(FileOutcome::Entrylist { drivers })
 }
 
 fn filename_to_timestamp(filename: &str) -> Result<DateTime<Utc>> {
-    Ok(
-        NaiveDateTime::parse_from_str(&filename[0..13], "%y%m%d_%H%M%S")
-            .context(anyhow!("Failed to parse datetime from filename"))?
-            .and_local_timezone(Local)
-            .earliest()
-            .context(anyhow!("Failed to convert datetime to local timezone"))?
-            .with_timezone(&Utc),
+    Ok(NaiveDateTime::parse_from_str(
+        filename.get(0..13).context("Filename too short")?,
+        "%y%m%d_%H%M%S",
+    )
+    .context(anyhow!("Failed to parse datetime from filename"))?
+    .and_local_timezone(Local)
+    .earliest()
+    .context(anyhow!("Failed to convert datetime to local timezone"))?
+    .with_timezone(&Utc))
+}
+
+// `YYMMDD_HHMMSS_` and then the session type or `entrylist`. Every byte is
+// checked, so there's no room for path separators or `..` anywhere.
+fn is_results_filename(filename: &str) -> bool {
+    let Some((prefix, suffix)) = filename.split_at_checked(14) else {
+        return false;
+    };
+    let prefix_ok = prefix.bytes().enumerate().all(|(index, byte)| match index {
+        6 | 13 => byte == b'_',
+        _ => byte.is_ascii_digit(),
+    });
+    prefix_ok && matches!(suffix, "P.json" | "Q.json" | "R.json" | "entrylist.json")
+}
+
+// What's known about a file that was imported before
+struct KnownFile {
+    directory: String,
+    size: Option<i64>,
+    mtime: Option<i64>,
+    hash: Option<String>,
+}
+
+async fn get_known_file(
+    filename: &str,
+    results_dir: &ResultsDir,
+    conn: &mut SqliteConnection,
+) -> Result<Option<KnownFile>> {
+    let directory = results_dir.key();
+    // Files known from before directories were tracked have an empty one
+    Ok(sqlx::query_as!(
+        KnownFile,
+        "SELECT directory, size, mtime, hash
+        FROM known_files
+        WHERE path = ?1 AND directory IN (?2, '')
+        ORDER BY directory DESC
+        LIMIT 1;",
+        filename,
+        directory
     )
+    .fetch_optional(&mut *conn)
+    .await?)
 }
 
-async fn check_file(path: impl AsRef<Path>, conn: &mut SqliteConnection) -> Result<()> {
+// For a file that was written again without changing what's in it
+async fn update_known_file(
+    filename: &str,
+    results_dir: &ResultsDir,
+    known: &KnownFile,
+    stamp: &FileStamp,
+    conn: &mut SqliteConnection,
+) -> Result<()> {
+    let directory = results_dir.key();
+    sqlx::query!(
+        "UPDATE known_files SET directory = ?, size = ?, mtime = ?, hash = ?
+        WHERE directory = ? AND path = ?;",
+        directory,
+        stamp.size,
+        stamp.mtime,
+        stamp.hash,
+        known.directory,
+        filename
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn check_file(
+    path: impl AsRef<Path>,
+    results_dir: &ResultsDir,
+    conn: &mut SqliteConnection,
+    events: &EventSender,
+) -> Result<FileOutcome> {
     let filename = path
         .as_ref()
         .file_name()
-        .unwrap()
+        .context("Not a file")?
         .to_string_lossy()
         .into_owned();
-    if !filename.ends_with("P.json")
-        && !filename.ends_with("Q.json")
-        && !filename.ends_with("R.json")
-        && !filename.ends_with("entrylist.json")
-    {
+    if !is_results_filename(&filename) {
         debug!("Skipping file: {} (wrong filename format)", filename);
-        return Ok(());
+        return Ok(FileOutcome::Ignored);
     };
-    let known = sqlx::query!("SELECT path FROM known_files WHERE path = ?;", filename)
-        .fetch_optional(&mut *conn)
-        .await?;
-    if known.is_some() {
+    let directory = results_dir.key();
+    let deleted = sqlx::query!(
+        "SELECT path FROM deleted_sessions WHERE directory = ? AND path = ?;",
+        directory,
+        filename
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .is_some();
+    if deleted {
+        info!("Skipping file: {} (session deleted by admin)", filename);
+        return Ok(FileOutcome::Deleted);
+    }
+    // Watcher events also come in for files that are gone again
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("Failed to read file: {}: {}", filename, e);
+            return Ok(FileOutcome::Unreadable {
+                error: e.to_string(),
+            });
+        }
+    };
+    let known = get_known_file(&filename, results_dir, &mut *conn).await?;
+    if known.as_ref().is_some_and(|known| {
+        known.size == Some(file_size(&metadata))
+            && known.mtime.is_some()
+            && known.mtime == file_mtime(&metadata)
+    }) {
         info!("Skipping file: {} (already in database)", filename);
-        return Ok(());
+        return Ok(FileOutcome::AlreadyKnown);
     }
-    let mut filename_chars = filename.chars();
-    match filename_chars.nth(14) {
-        Some('P' | 'Q' | 'R') => {
-            info!("Processing results file: {}", filename);
-            assert_eq!(filename_chars.collect::<String>(), ".json");
-            let session_results = match read_file(&path) {
-                Ok(session_results) => session_results,
-                Err(e) => {
-                    warn!("Failed to read results file: {}\n{}", e, e.root_cause());
-                    return Ok(());
-                }
-            };
-            add_session_results(&mut *conn, session_results, &filename).await?;
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read file: {}: {}", filename, e);
+            return Ok(FileOutcome::Unreadable {
+                error: e.to_string(),
+            });
         }
-        Some('e') => {
-            info!("Processing entrylist file: {}", filename);
-            assert_eq!(filename_chars.collect::<String>(), "ntrylist.json");
-            let entrylist: json::EntryList = match read_file(&path) {
-                Ok(entrylist) => entrylist,
-                Err(e) => {
-                    warn!("Failed to read entrylist file: {}", e.root_cause());
-                    return Ok(());
-                }
-            };
-            add_entrylist(&mut *conn, entrylist, &filename).await?;
+    };
+    let stamp = FileStamp::new(&metadata, &bytes);
+    if let Some(known) = known {
+        if known.hash.as_ref().is_some_and(|hash| *hash == stamp.hash) {
+            update_known_file(&filename, results_dir, &known, &stamp, &mut *conn).await?;
+            info!("Skipping file: {} (already in database)", filename);
+            return Ok(FileOutcome::AlreadyKnown);
+        }
+        // Files imported before hashes were kept can't be told apart from
+        // ones that changed since, so they're imported again once
+        if known.hash.is_none() {
+            info!("File imported before its contents were kept: {}", filename);
+        } else {
+            info!("File changed since it was imported: {}", filename);
         }
-        _ => unreachable!("Invalid filename format"),
+    }
+    let quarantined_hash = sqlx::query!(
+        "SELECT hash FROM quarantined_files WHERE directory = ? AND path = ?;",
+        directory,
+        filename
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|row| row.hash);
+    if quarantined_hash.is_some_and(|hash| hash == stamp.hash) {
+        info!("Skipping file: {} (quarantined)", filename);
+        return Ok(FileOutcome::Quarantined);
+    }
+    ingest_file(&bytes, &filename, results_dir, &stamp, conn, events).await
+}
+
+// What a session's results file would have been called
+fn session_filename(timestamp: i64, session_type: &str) -> Option<String> {
+    let timestamp = Local.timestamp_opt(timestamp, 0).earliest()?;
+    Some(format!(
+        "{}_{session_type}.json",
+        timestamp.format("%y%m%d_%H%M%S")
+    ))
+}
+
+// The directory and name of the file a session was imported from, if it's
+// known
+async fn session_file(
+    session_id: i64,
+    timestamp: i64,
+    session_type: &str,
+    conn: &mut SqliteConnection,
+) -> Result<Option<(String, String)>> {
+    let file = sqlx::query!(
+        "SELECT directory, path FROM known_files WHERE session_id = ?;",
+        session_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(file) = file {
+        return Ok(Some((file.directory, file.path)));
+    }
+    // Files imported before they were linked to their session can still be
+    // found by name
+    let Some(filename) = session_filename(timestamp, session_type) else {
+        return Ok(None);
     };
-    Ok(())
+    let directories = sqlx::query!(
+        "SELECT directory FROM known_files WHERE path = ? AND session_id IS NULL;",
+        filename
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in directories {
+        if !row.directory.is_empty() {
+            return Ok(Some((row.directory, filename)));
+        }
+        // And from before directories were tracked, by looking
+        if let Some(results_dir) = configured_dirs()?
+            .into_iter()
+            .find(|results_dir| results_dir.path.join(&filename).exists())
+        {
+            return Ok(Some((results_dir.key(), filename)));
+        }
+    }
+    Ok(None)
 }
 
-async fn check_directory(results_dir: impl AsRef<Path>, pool: &SqlitePool) -> Result<()> {
-    let mut conn = pool.acquire().await?;
+// Deletes a session on an admin's say-so. If it came from a file, the file is
+// kept out of the database until the session is restored, otherwise it's gone
+// for good. Returns whether there was such a session.
+async fn remove_session(session_id: i64, conn: &mut SqliteConnection) -> Result<bool> {
+    let mut tx = conn.begin().await?;
+    let Some(session) = sqlx::query!(
+        "SELECT track, type AS session_type, timestamp, server_name FROM sessions WHERE id = ?;",
+        session_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(false);
+    };
+    match session_file(
+        session_id,
+        session.timestamp,
+        &session.session_type,
+        &mut tx,
+    )
+    .await?
+    {
+        Some((directory, filename)) => {
+            let now = Utc::now().timestamp();
+            sqlx::query!(
+                "INSERT OR REPLACE INTO deleted_sessions (
+                    directory, path, track, type, timestamp, server_name, deleted_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?);",
+                directory,
+                filename,
+                session.track,
+                session.session_type,
+                session.timestamp,
+                session.server_name,
+                now
+            )
+            .execute(&mut *tx)
+            .await?;
+            info!("Deleted session {} from {}", session_id, filename);
+        }
+        None => info!("Deleted session {} for good", session_id),
+    }
+    delete_session(&mut *tx, session_id).await?;
+    personal_bests::refresh(&mut tx, &session.track, None).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+// Brings back a session an admin deleted, by importing its file again.
+// Returns None if there's no such deleted session, only files of those get
+// looked at.
+async fn restore_session(
+    directory: &str,
+    filename: &str,
+    conn: &mut SqliteConnection,
+    events: &EventSender,
+) -> Result<Option<FileOutcome>> {
+    let mut tx = conn.begin().await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM deleted_sessions WHERE directory = ? AND path = ? RETURNING path;",
+        directory,
+        filename
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some();
+    if !deleted {
+        return Ok(None);
+    }
+    let results_dir = results_dir_for(Path::new(directory))?;
+    sqlx::query!(
+        "DELETE FROM known_files WHERE directory IN (?, '') AND path = ?;",
+        directory,
+        filename
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    info!("Restoring session from {}", filename);
+    check_file(results_dir.path.join(filename), &results_dir, conn, events)
+        .await
+        .map(Some)
+}
+
+// Gives a quarantined file another go, for after a parser fix
+async fn retry_quarantined_file(
+    directory: &str,
+    filename: &str,
+    conn: &mut SqliteConnection,
+    events: &EventSender,
+) -> Result<FileOutcome> {
+    let results_dir = results_dir_for(Path::new(directory))?;
+    let path = results_dir.path.join(filename);
+    let bytes =
+        fs::read(&path).with_context(|| format!("Failed to read file {}", path.display()))?;
+    let metadata = fs::metadata(&path)?;
+    let stamp = FileStamp::new(&metadata, &bytes);
+    ingest_file(&bytes, filename, &results_dir, &stamp, conn, events).await
+}
+
+async fn ingest_file(
+    bytes: &[u8],
+    filename: &str,
+    results_dir: &ResultsDir,
+    stamp: &FileStamp,
+    conn: &mut SqliteConnection,
+    events: &EventSender,
+) -> Result<FileOutcome> {
+    let result = if filename.ends_with("entrylist.json") {
+        info!("Processing entrylist file: {}", filename);
+        let entrylist: json::EntryList = match parse_file(bytes, filename) {
+            Ok(entrylist) => entrylist,
+            Err(e) => {
+                warn!("Failed to read entrylist file: {}", e.root_cause());
+                quarantine_file(filename, results_dir, &stamp.hash, &e, conn).await?;
+                return Ok(FileOutcome::Unreadable {
+                    error: format!("{e:#}"),
+                });
+            }
+        };
+        add_entrylist(&mut *conn, entrylist, filename, results_dir, stamp, events).await
+    } else {
+        info!("Processing results file: {}", filename);
+        let session_results = match parse_file(bytes, filename) {
+            Ok(session_results) => session_results,
+            Err(e) => {
+                warn!("Failed to read results file: {}\n{}", e, e.root_cause());
+                quarantine_file(filename, results_dir, &stamp.hash, &e, conn).await?;
+                return Ok(FileOutcome::Unreadable {
+                    error: format!("{e:#}"),
+                });
+            }
+        };
+        add_session_results(
+            &mut *conn,
+            session_results,
+            filename,
+            results_dir,
+            stamp,
+            events,
+        )
+        .await
+    };
+    // A file that parses but doesn't go into the database is kept out the
+    // same way, rather than being tried again on every change in the directory
+    match result {
+        Ok(outcome) => Ok(outcome),
+        Err(e) => {
+            warn!("Failed to import file: {}: {:#}", filename, e);
+            quarantine_file(filename, results_dir, &stamp.hash, &e, conn).await?;
+            Ok(FileOutcome::Unreadable {
+                error: format!("{e:#}"),
+            })
+        }
+    }
+}
+
+async fn check_directory(
+    results_dir: &ResultsDir,
+    conn: &mut SqliteConnection,
+    events: &EventSender,
+) -> Result<()> {
     // Iterate over all `*[PQR].json` files in the results directory
-    let mut files = read_dir(&results_dir)?.collect::<std::io::Result<Vec<DirEntry>>>()?;
+    let mut files = read_dir(&results_dir.path)?.collect::<std::io::Result<Vec<DirEntry>>>()?;
     // Sort so that they are processed in order, otherwise the superset of previous file detection won't work.
     files.sort_unstable_by_key(DirEntry::path);
     for entry in files {
-        check_file(entry.path(), &mut conn).await?;
+        check_file(entry.path(), results_dir, &mut *conn, events).await?;
     }
-    info!("All files in {} processed", results_dir.as_ref().display());
+    info!("All files in {} processed", results_dir.path.display());
     Ok(())
 }
 
-async fn watcher_task() -> Result<()> {
-    let dburl = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let results_path = env::var("RESULTS_PATH").expect("RESULTS_PATH must be set");
+async fn watcher_task(results_dir: ResultsDir, event_sender: EventSender) -> Result<()> {
     // Make single connection
-    let mut conn = SqliteConnection::connect(&dburl).await?;
+    let mut conn = SqliteConnection::connect(config::get().database_url()?).await?;
     let (mut debouncer, mut file_events) =
         AsyncDebouncer::new_with_channel(Duration::from_secs(1), Some(Duration::from_secs(1)))
             .await?;
     debouncer
         .watcher()
-        .watch(results_path.as_ref(), RecursiveMode::Recursive)
-        .unwrap();
+        .watch(&results_dir.path, RecursiveMode::Recursive)?;
     while let Some(result) = file_events.recv().await {
         if let Ok(events) = result {
             debug!("Received events: {:?}", events);
             for event in events {
-                check_file(event.path, &mut conn).await?;
+                // One file going wrong shouldn't stop the watching
+                if let Err(e) =
+                    check_file(&event.path, &results_dir, &mut conn, &event_sender).await
+                {
+                    error!("Failed to check file: {}: {:?}", event.path.display(), e);
+                }
             }
         }
     }
     Ok(())
 }
 
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    after_help = "A running server doesn't hear about what the other subcommands change, \
+        it shows that once its cached pages expire, see `max_age_secs` in the config file."
+)]
+struct Cli {
+    /// Configuration file, environment variables override what's in it
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Import new results files, keep watching for more and run the web
+    /// server. This is what happens without a subcommand too.
+    Serve,
+    /// Import the given results and entrylist files, or all of them in the
+    /// given directories, and exit
+    Import {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Forget everything that was imported from files and import all of the
+    /// results directories again
+    Reimport,
+    /// Recalculate the personal best laps and sectors from all laps
+    RebuildAggregates,
+    /// Parse a results or entrylist file and show what would be imported,
+    /// without touching the database
+    Check { file: PathBuf },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Read .env file
-    dotenvy::dotenv()?;
+    let cli = Cli::parse();
 
-    let dburl = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let results_path = env::var("RESULTS_PATH").expect("RESULTS_PATH must be set");
+    // Read .env file, if there is one
+    if let Err(e) = dotenvy::dotenv() {
+        if !e.not_found() {
+            return Err(e.into());
+        }
+    }
 
     // Initialize logger
     env_logger::init();
 
+    config::load(cli.config.as_deref())?;
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve().await,
+        Command::Import { paths } => import(paths).await,
+        Command::Reimport => reimport().await,
+        Command::RebuildAggregates => rebuild_aggregates().await,
+        Command::Check { file } => check(&file),
+    }
+}
+
+async fn connect() -> Result<SqlitePool> {
     // Connect to the database and run migrations
-    let pool = SqlitePool::connect(&dburl).await?;
+    let pool = SqlitePool::connect(config::get().database_url()?).await?;
     sqlx::migrate!("./migrations").run(&pool).await?;
+    Ok(pool)
+}
 
-    // Check for new files
-    check_directory(&results_path, &pool).await?;
+async fn serve() -> Result<()> {
+    let results_dirs = results_dirs()?;
+    let pool = connect().await?;
 
-    // Start watcher task
-    tokio::spawn(async move {
-        watcher_task().await.unwrap();
-    });
+    // Newly added sessions, personal bests and such get sent to the SSE
+    // endpoint, and tell the web server to drop its cached pages
+    let events = events::channel();
+
+    // Check for new files. The boards are still worth serving if that goes
+    // wrong, the watchers pick up from there.
+    for results_dir in &results_dirs {
+        if let Err(e) = check_directory(results_dir, &mut *pool.acquire().await?, &events).await {
+            error!(
+                "Failed to check directory: {}: {:?}",
+                results_dir.path.display(),
+                e
+            );
+        }
+    }
+
+    // Start a watcher task for every directory
+    for results_dir in results_dirs {
+        let watcher_events = events.clone();
+        tokio::spawn(async move {
+            let path = results_dir.path.clone();
+            if let Err(e) = watcher_task(results_dir, watcher_events).await {
+                error!("Stopped watching {}: {:?}", path.display(), e);
+            }
+        });
+    }
+
+    // Start live timing task, if configured
+    let live_timing_events = events.clone();
+    tokio::spawn(live_timing::live_timing_task(live_timing_events));
+
+    appserver::run(pool, events).await
+}
+
+async fn import(paths: Vec<PathBuf>) -> Result<()> {
+    let pool = connect().await?;
+    let mut conn = pool.acquire().await?;
+    // Nothing is listening when importing from the command line
+    let events = events::channel();
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            for entry in read_dir(&path)? {
+                files.push(entry?.path());
+            }
+        } else {
+            files.push(path);
+        }
+    }
+    // Sort so that they are processed in order, otherwise the superset of previous file detection won't work.
+    files.sort_unstable_by(|a, b| a.file_name().cmp(&b.file_name()));
+    for file in files {
+        // Files in one of the configured directories get its label
+        let results_dir = results_dir_for(file.parent().context("Not a file")?)?;
+        check_file(&file, &results_dir, &mut conn, &events).await?;
+    }
+    Ok(())
+}
+
+async fn reimport() -> Result<()> {
+    let mut results_dirs = results_dirs()?;
+    results_dirs.extend(upload_dir()?);
+    let pool = connect().await?;
+    let mut conn = pool.acquire().await?;
+    let events = events::channel();
+    // All or nothing, a failed reimport leaves the database as it was
+    let mut tx = conn.begin().await?;
+    // Live timing sessions don't have a file to come back from, and get
+    // replaced when their results file is imported anyway.
+    let deleted = sqlx::query!(
+        "DELETE FROM sessions
+        WHERE id NOT IN (SELECT session_id FROM laps WHERE provisional = 1);"
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query!("DELETE FROM known_files;")
+        .execute(&mut *tx)
+        .await?;
+    info!("Deleted {} sessions, reimporting", deleted);
+    for results_dir in &results_dirs {
+        check_directory(results_dir, &mut tx, &events).await?;
+    }
+    personal_bests::rebuild(&mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
 
-    appserver::run(pool).await
+async fn rebuild_aggregates() -> Result<()> {
+    let pool = connect().await?;
+    let mut conn = pool.acquire().await?;
+    let mut tx = conn.begin().await?;
+    personal_bests::rebuild(&mut tx).await?;
+    tx.commit().await?;
+    info!("Personal bests rebuilt");
+    Ok(())
+}
+
+fn check(path: &Path) -> Result<()> {
+    let filename = path
+        .file_name()
+        .context("Not a file")?
+        .to_string_lossy()
+        .into_owned();
+    if filename.ends_with("entrylist.json") {
+        let entrylist: json::EntryList = read_file(path)?;
+        let drivers = entrylist
+            .entries
+            .iter()
+            .map(|entry| entry.drivers.len())
+            .sum::<usize>();
+        println!(
+            "{}: entry list with {} entries, {} drivers",
+            filename,
+            entrylist.entries.len(),
+            drivers
+        );
+        return Ok(());
+    }
+
+    let session_results: json::SessionResults = read_file(path)?;
+    match filename_to_timestamp(&filename) {
+        Ok(timestamp) => println!(
+            "{}: session at {}",
+            filename,
+            timestamp.with_timezone(&Local)
+        ),
+        Err(e) => println!("{}: {} (would not be imported)", filename, e),
+    }
+    println!(
+        "  {} on {} ({}), {}",
+        session_results.session_type,
+        session_results.track_name,
+        session_results.server_name,
+        if session_results.session_result.is_wet_session == 0 {
+            "dry"
+        } else {
+            "wet"
+        }
+    );
+    let lines = &session_results.session_result.leader_board_lines;
+    let mut car_driver_to_name = HashMap::new();
+    for line in lines {
+        for (index, driver) in line.car.drivers.iter().enumerate() {
+            car_driver_to_name.insert(
+                (line.car.car_id, i64::try_from(index).unwrap()),
+                format!("{} {}", driver.first_name, driver.last_name),
+            );
+        }
+    }
+    let drivers = lines
+        .iter()
+        .flat_map(|line| &line.car.drivers)
+        .map(|driver| driver.steam_id)
+        .collect::<HashSet<_>>();
+    println!("  {} cars, {} drivers", lines.len(), drivers.len());
+    let laps = &session_results.laps;
+    let valid_laps = laps.iter().filter(|lap| lap.is_valid_for_best).count();
+    println!("  {} laps, {} valid", laps.len(), valid_laps);
+    if let Some(fastest) = laps
+        .iter()
+        .filter(|lap| lap.is_valid_for_best)
+        .min_by_key(|lap| lap.laptime)
+    {
+        let name = car_driver_to_name
+            .get(&(fastest.car_id, fastest.driver_index))
+            .map_or("unknown driver", String::as_str);
+        println!(
+            "  Fastest lap {} by {}",
+            appserver::format_duration(fastest.laptime),
+            name
+        );
+    }
+    let post_race_penalties = session_results
+        .post_race_penalties
+        .as_ref()
+        .map_or(0, Vec::len);
+    println!(
+        "  {} penalties, {} post race penalties",
+        session_results.penalties.len(),
+        post_race_penalties
+    );
+    // Same as what add_session_results bails out on
+    let orphaned_laps = laps
+        .iter()
+        .filter(|lap| !car_driver_to_name.contains_key(&(lap.car_id, lap.driver_index)))
+        .count();
+    if orphaned_laps > 0 {
+        return Err(anyhow!(
+            "{} laps have no matching car and driver, the file would not be imported",
+            orphaned_laps
+        ));
+    }
+    if laps.is_empty() {
+        println!("  No laps, the session would be skipped");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_filenames() {
+        for filename in [
+            "240501_120000_P.json",
+            "240501_120000_Q.json",
+            "240501_120000_R.json",
+            "240501_120000_entrylist.json",
+        ] {
+            assert!(is_results_filename(filename), "{filename}");
+        }
+        for filename in [
+            "",
+            "P.json",
+            "240501_120000_X.json",
+            "240501_120000_P.json.bak",
+            "240501_120000-P.json",
+            "240501-120000_P.json",
+            "24050a_120000_P.json",
+            "240501_120000/P.json",
+            "240501_120000\\P.json",
+            "../../../etc_P.json",
+            "..\\..\\1_120000_P.json",
+            "/tmp/00_120000_P.json",
+        ] {
+            assert!(!is_results_filename(filename), "{filename}");
+        }
+    }
+
+    const STEAM_ID: i64 = 76_561_197_960_287_930;
+
+    fn driver_json(steam_id: i64, first_name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "firstName": first_name,
+            "lastName": "Driver",
+            "shortName": first_name.get(..3).unwrap_or(first_name).to_uppercase(),
+            "playerId": format!("S{steam_id}"),
+        })
+    }
+
+    // A session at Monza with a car for every driver, classified in the order
+    // they're given in, and their laps in the order they're given in
+    fn results_json(session_type: &str, cars: &[(i64, &str, &[u64])]) -> serde_json::Value {
+        let car_id = |index: usize| 1001 + i64::try_from(index).unwrap();
+        let leader_board_lines = cars
+            .iter()
+            .enumerate()
+            .map(|(index, (steam_id, first_name, laptimes))| {
+                let driver = driver_json(*steam_id, first_name);
+                let total_time: u64 = laptimes.iter().sum();
+                serde_json::json!({
+                    "car": {
+                        "carId": car_id(index),
+                        "raceNumber": index + 1,
+                        "carModel": 30,
+                        "cupCategory": 0,
+                        "carGroup": "GT3",
+                        "teamName": "",
+                        "drivers": [driver],
+                    },
+                    "currentDriver": driver,
+                    "timing": {
+                        "bestLap": laptimes.iter().min(),
+                        "totalTime": total_time,
+                        "lapCount": laptimes.len(),
+                    },
+                    "missingMandatoryPitstop": 0,
+                    "driverTotalTimes": [total_time],
+                })
+            })
+            .collect::<Vec<_>>();
+        let laps = cars
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (_, _, laptimes))| {
+                laptimes.iter().map(move |laptime_ms| {
+                    serde_json::json!({
+                        "carId": car_id(index),
+                        "driverIndex": 0,
+                        "laptime": laptime_ms,
+                        "isValidForBest": true,
+                        "splits": [
+                            laptime_ms / 3,
+                            laptime_ms / 3,
+                            laptime_ms - 2 * (laptime_ms / 3),
+                        ],
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "sessionType": session_type,
+            "trackName": "monza",
+            "serverName": "Test server",
+            "sessionResult": {
+                "isWetSession": 0,
+                "leaderBoardLines": leader_board_lines,
+            },
+            "laps": laps,
+            "penalties": [],
+        })
+    }
+
+    fn file_bytes(results: &serde_json::Value) -> Vec<u8> {
+        // The game writes these with a BOM
+        let mut bytes = vec![0xEF, 0xBB, 0xBF];
+        bytes.extend(results.to_string().into_bytes());
+        bytes
+    }
+
+    // A qualifying session with one driver and one lap
+    fn results_file(laptime_ms: u64) -> Vec<u8> {
+        file_bytes(&results_json("Q", &[(STEAM_ID, "Test", &[laptime_ms])]))
+    }
+
+    // Writes a file next to the one `test_setup` returns and imports it
+    async fn import(
+        path: &Path,
+        filename: &str,
+        bytes: Vec<u8>,
+        results_dir: &ResultsDir,
+        conn: &mut SqliteConnection,
+    ) -> FileOutcome {
+        let path = path.with_file_name(filename);
+        fs::write(&path, bytes).unwrap();
+        check_file(&path, results_dir, conn, &events::channel())
+            .await
+            .unwrap()
+    }
+
+    async fn test_setup(name: &str) -> (PathBuf, ResultsDir, SqliteConnection) {
+        let dir =
+            std::env::temp_dir().join(format!("acc_hotlap_boards_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let results_dir = ResultsDir::new(&dir, None).unwrap();
+        let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&mut conn).await.unwrap();
+        (dir.join("240501_120000_Q.json"), results_dir, conn)
+    }
+
+    async fn known_hash(results_dir: &ResultsDir, conn: &mut SqliteConnection) -> Option<String> {
+        get_known_file("240501_120000_Q.json", results_dir, conn)
+            .await
+            .unwrap()
+            .unwrap()
+            .hash
+    }
+
+    #[tokio::test]
+    async fn check_file_changed() {
+        let (path, results_dir, mut conn) = test_setup("changed").await;
+        let events = events::channel();
+        fs::write(&path, results_file(100_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        let FileOutcome::Session(first) = outcome else {
+            panic!("Not imported: {outcome:?}");
+        };
+        assert_eq!(first.replaced_session_id, None);
+
+        fs::write(&path, results_file(99_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        let FileOutcome::Session(second) = outcome else {
+            panic!("Not imported again: {outcome:?}");
+        };
+        assert_eq!(second.replaced_session_id, Some(first.session_id));
+        let sessions = sqlx::query_scalar!("SELECT COUNT(*) FROM sessions;")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(sessions, 1);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn check_file_unchanged() {
+        let (path, results_dir, mut conn) = test_setup("unchanged").await;
+        let events = events::channel();
+        fs::write(&path, results_file(100_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+
+        // Written again with the same content, only the mtime differs
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, FileOutcome::AlreadyKnown), "{outcome:?}");
+        let sessions = sqlx::query_scalar!("SELECT COUNT(*) FROM sessions;")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(sessions, 1);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn check_file_legacy() {
+        let (path, results_dir, mut conn) = test_setup("legacy").await;
+        let events = events::channel();
+        fs::write(&path, results_file(100_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        let FileOutcome::Session(first) = outcome else {
+            panic!("Not imported: {outcome:?}");
+        };
+
+        // What a file imported before its contents and session were kept looks
+        // like. Written again since, which can't be told from the database, so
+        // it's imported again and takes the place of the session it had.
+        sqlx::query!(
+            "UPDATE known_files
+            SET directory = '', size = NULL, mtime = NULL, hash = NULL, session_id = NULL;"
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        fs::write(&path, results_file(99_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        let FileOutcome::Session(second) = outcome else {
+            panic!("Not imported again: {outcome:?}");
+        };
+        assert_eq!(second.replaced_session_id, Some(first.session_id));
+        let laptimes = sqlx::query_scalar!("SELECT time_ms FROM laps;")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(laptimes, [99_000]);
+
+        // And from then on it's known like any other file
+        assert!(known_hash(&results_dir, &mut conn).await.is_some());
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, FileOutcome::AlreadyKnown), "{outcome:?}");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn classification() {
+        let (path, results_dir, mut conn) = test_setup("classification").await;
+        let other_steam_id = STEAM_ID + 1;
+        let results = results_json(
+            "R",
+            &[
+                (STEAM_ID, "Winner", &[101_000, 100_000]),
+                (other_steam_id, "Second", &[99_000, 103_000]),
+            ],
+        );
+        let outcome = import(
+            &path,
+            "240501_120000_R.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        let cars = sqlx::query!(
+            "SELECT position, lap_count, total_time_ms, best_lap_ms, finishing_steam_id
+            FROM cars
+            ORDER BY position;"
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        let cars = cars
+            .into_iter()
+            .map(|car| {
+                (
+                    car.position,
+                    car.lap_count,
+                    car.total_time_ms,
+                    car.best_lap_ms,
+                    car.finishing_steam_id,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cars,
+            [
+                (
+                    Some(1),
+                    Some(2),
+                    Some(201_000),
+                    Some(100_000),
+                    Some(STEAM_ID)
+                ),
+                (
+                    Some(2),
+                    Some(2),
+                    Some(202_000),
+                    Some(99_000),
+                    Some(other_steam_id)
+                ),
+            ]
+        );
+        let car_drivers =
+            sqlx::query_scalar!("SELECT steam_id FROM car_drivers ORDER BY steam_id;")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(car_drivers, [STEAM_ID, other_steam_id]);
+
+        // Practice isn't classified
+        let results = results_json("P", &[(STEAM_ID, "Winner", &[98_000])]);
+        let outcome = import(
+            &path,
+            "240501_100000_P.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        let FileOutcome::Session(summary) = outcome else {
+            panic!("Not imported: {outcome:?}");
+        };
+        let positions = sqlx::query_scalar!(
+            "SELECT position FROM cars WHERE session_id = ?;",
+            summary.session_id
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(positions, [None]);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn penalties() {
+        let (path, results_dir, mut conn) = test_setup("penalties").await;
+        let other_steam_id = STEAM_ID + 1;
+        let mut results = results_json(
+            "R",
+            &[
+                (STEAM_ID, "Cutter", &[100_000, 98_000]),
+                (other_steam_id, "Late", &[101_000, 102_000]),
+            ],
+        );
+        // A drive through served on the second lap, and time added afterwards
+        results["penalties"] = serde_json::json!([{
+            "carId": 1001,
+            "driverIndex": 0,
+            "reason": "Cutting",
+            "penalty": "DriveThrough",
+            "penaltyValue": 3,
+            "violationInLap": 1,
+            "clearedInLap": 2,
+        }]);
+        results["postRacePenalties"] = serde_json::json!([{
+            "carId": 1002,
+            "driverIndex": 0,
+            "reason": "Speeding",
+            "penalty": "PostRaceTime",
+            "penaltyValue": 5,
+            "violationInLap": 0,
+            "clearedInLap": 0,
+        }]);
+        let outcome = import(
+            &path,
+            "240501_120000_R.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        let penalties = sqlx::query!(
+            r#"SELECT steam_id, penalty, post_race AS "post_race: bool"
+            FROM penalties
+            ORDER BY id;"#
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.steam_id, row.penalty, row.post_race))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            penalties,
+            [
+                (Some(STEAM_ID), "DriveThrough".to_string(), false),
+                (Some(other_steam_id), "PostRaceTime".to_string(), true),
+            ]
+        );
+        let penalty_laps =
+            sqlx::query_scalar!("SELECT time_ms FROM laps WHERE penalty_served = 1;")
+                .fetch_all(&mut conn)
+                .await
+                .unwrap();
+        assert_eq!(penalty_laps, [98_000]);
+        // The lap the penalty was served on only counts for boards that don't
+        // leave those out
+        let personal_bests = sqlx::query!(
+            "SELECT exclude_penalty_laps, time_ms
+            FROM personal_best_laps
+            WHERE steam_id = ?
+            ORDER BY exclude_penalty_laps;",
+            STEAM_ID
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.exclude_penalty_laps, row.time_ms))
+        .collect::<Vec<_>>();
+        assert_eq!(personal_bests, [(0, 98_000), (1, 100_000)]);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn deleted_sessions() {
+        let (path, results_dir, mut conn) = test_setup("deleted").await;
+        let events = events::channel();
+        fs::write(&path, results_file(100_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        let FileOutcome::Session(summary) = outcome else {
+            panic!("Not imported: {outcome:?}");
+        };
+        assert!(remove_session(summary.session_id, &mut conn).await.unwrap());
+        assert!(!remove_session(summary.session_id, &mut conn).await.unwrap());
+
+        // The file is left alone from then on, even when it changes
+        fs::write(&path, results_file(99_000)).unwrap();
+        let outcome = check_file(&path, &results_dir, &mut conn, &events)
+            .await
+            .unwrap();
+        assert!(matches!(outcome, FileOutcome::Deleted), "{outcome:?}");
+        let sessions = sqlx::query_scalar!("SELECT COUNT(*) FROM sessions;")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(sessions, 0);
+
+        // Only deleted sessions can be restored, not just any file
+        let directory = results_dir.key();
+        let restored = restore_session(&directory, "240501_130000_Q.json", &mut conn, &events)
+            .await
+            .unwrap();
+        assert!(restored.is_none());
+        let deleted = sqlx::query_scalar!("SELECT path FROM deleted_sessions;")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(deleted, ["240501_120000_Q.json"]);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn exclusions_survive_reimport() {
+        let (path, results_dir, mut conn) = test_setup("exclusions").await;
+        let results = results_json("Q", &[(STEAM_ID, "Test", &[100_000, 98_000])]);
+        let outcome = import(
+            &path,
+            "240501_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        let FileOutcome::Session(summary) = outcome else {
+            panic!("Not imported: {outcome:?}");
+        };
+        // What the admin page does for the second lap
+        sqlx::query!(
+            "INSERT INTO lap_exclusions (
+                session_timestamp, server_name, steam_id, lap_number, reason
+            )
+            SELECT timestamp, server_name, ?, 2, 'Cut the chicane'
+            FROM sessions
+            WHERE id = ?;",
+            STEAM_ID,
+            summary.session_id
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+        // The file gets written again with another lap on it
+        let results = results_json("Q", &[(STEAM_ID, "Test", &[100_000, 98_000, 99_000])]);
+        let outcome = import(
+            &path,
+            "240501_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        let excluded = sqlx::query_scalar!("SELECT time_ms FROM laps WHERE excluded = 1;")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(excluded, [98_000]);
+        let personal_bests = sqlx::query_scalar!(
+            "SELECT time_ms FROM personal_best_laps WHERE exclude_penalty_laps = 0;"
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(personal_bests, [99_000]);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    // Steam IDs and lap times on the boards that include penalty laps
+    async fn personal_bests(conn: &mut SqliteConnection) -> Vec<(i64, i64)> {
+        sqlx::query!(
+            "SELECT steam_id, time_ms
+            FROM personal_best_laps
+            WHERE exclude_penalty_laps = 0
+            ORDER BY steam_id;"
+        )
+        .fetch_all(conn)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|row| (row.steam_id, row.time_ms))
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn linked_accounts() {
+        let (path, results_dir, mut conn) = test_setup("linked").await;
+        let second_account = STEAM_ID + 1;
+        let results = results_json(
+            "Q",
+            &[
+                (STEAM_ID, "Main", &[100_000]),
+                (second_account, "Second", &[98_000]),
+            ],
+        );
+        let outcome = import(
+            &path,
+            "240501_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        assert_eq!(
+            personal_bests(&mut conn).await,
+            [(STEAM_ID, 100_000), (second_account, 98_000)]
+        );
+
+        // The laps of the second account count as the main account's
+        assert!(link_driver(second_account, STEAM_ID, &mut conn)
+            .await
+            .unwrap());
+        assert_eq!(personal_bests(&mut conn).await, [(STEAM_ID, 98_000)]);
+        assert_eq!(
+            main_account(&mut conn, second_account).await.unwrap(),
+            STEAM_ID
+        );
+        // Linked accounts can't have accounts linked to them
+        assert!(!link_driver(STEAM_ID, second_account, &mut conn)
+            .await
+            .unwrap());
+        assert!(!unlink_driver(STEAM_ID, &mut conn).await.unwrap());
+
+        assert!(unlink_driver(second_account, &mut conn).await.unwrap());
+        assert_eq!(
+            personal_bests(&mut conn).await,
+            [(STEAM_ID, 100_000), (second_account, 98_000)]
+        );
+
+        // A banned account doesn't get back on the boards by being linked
+        sqlx::query!(
+            "UPDATE drivers SET status = 'banned' WHERE steam_id = ?;",
+            second_account
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        assert!(!link_driver(second_account, STEAM_ID, &mut conn)
+            .await
+            .unwrap());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    async fn first_name(conn: &mut SqliteConnection) -> String {
+        sqlx::query_scalar!(
+            "SELECT first_name FROM drivers WHERE steam_id = ?;",
+            STEAM_ID
+        )
+        .fetch_one(conn)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn name_history() {
+        let (path, results_dir, mut conn) = test_setup("names").await;
+        for (filename, name, laptime_ms) in [
+            ("240501_120000_Q.json", "Renamed", 100_000),
+            // Imported later, but from before the rename
+            ("240401_120000_Q.json", "Original", 101_000),
+        ] {
+            let results = results_json("Q", &[(STEAM_ID, name, &[laptime_ms])]);
+            let outcome = import(
+                &path,
+                filename,
+                file_bytes(&results),
+                &results_dir,
+                &mut conn,
+            )
+            .await;
+            assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        }
+        assert_eq!(first_name(&mut conn).await, "Renamed");
+        let names = sqlx::query_scalar!(
+            "SELECT first_name FROM driver_names WHERE steam_id = ? ORDER BY last_seen;",
+            STEAM_ID
+        )
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(names, ["Original", "Renamed"]);
+
+        // What an admin set stays, the name is still kept
+        sqlx::query!(
+            "UPDATE drivers SET first_name = 'Edited', locked = 1 WHERE steam_id = ?;",
+            STEAM_ID
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        let results = results_json("Q", &[(STEAM_ID, "Newest", &[99_000])]);
+        let outcome = import(
+            &path,
+            "240601_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        assert_eq!(first_name(&mut conn).await, "Edited");
+        let newest = sqlx::query_scalar!(
+            "SELECT first_name FROM driver_names
+            WHERE steam_id = ?
+            ORDER BY last_seen DESC
+            LIMIT 1;",
+            STEAM_ID
+        )
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+        assert_eq!(newest, "Newest");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn driver_status() {
+        let (path, results_dir, mut conn) = test_setup("status").await;
+        let other_steam_id = STEAM_ID + 1;
+        let results = results_json(
+            "Q",
+            &[
+                (STEAM_ID, "Test", &[100_000]),
+                (other_steam_id, "Other", &[101_000]),
+            ],
+        );
+        let outcome = import(
+            &path,
+            "240501_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        for status in ["hidden", "banned"] {
+            sqlx::query!(
+                "UPDATE drivers SET status = ? WHERE steam_id = ?;",
+                status,
+                STEAM_ID
+            )
+            .execute(&mut conn)
+            .await
+            .unwrap();
+            personal_bests::refresh_driver(&mut conn, STEAM_ID)
+                .await
+                .unwrap();
+            assert_eq!(personal_bests(&mut conn).await, [(other_steam_id, 101_000)]);
+        }
+
+        // Laps that come in meanwhile stay off too, but are kept
+        let results = results_json("Q", &[(STEAM_ID, "Test", &[99_000])]);
+        let outcome = import(
+            &path,
+            "240502_120000_Q.json",
+            file_bytes(&results),
+            &results_dir,
+            &mut conn,
+        )
+        .await;
+        assert!(matches!(outcome, FileOutcome::Session(_)), "{outcome:?}");
+        assert_eq!(personal_bests(&mut conn).await, [(other_steam_id, 101_000)]);
+
+        sqlx::query!(
+            "UPDATE drivers SET status = 'active' WHERE steam_id = ?;",
+            STEAM_ID
+        )
+        .execute(&mut conn)
+        .await
+        .unwrap();
+        personal_bests::refresh_driver(&mut conn, STEAM_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            personal_bests(&mut conn).await,
+            [(STEAM_ID, 99_000), (other_steam_id, 101_000)]
+        );
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
 }
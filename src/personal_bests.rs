@@ -0,0 +1,135 @@
+use anyhow::Result;
+use sqlx::SqliteConnection;
+
+use crate::main_account;
+
+// Recalculates the personal best laps and sectors on a track, for all drivers
+// or just the one. Has to be called whenever laps on the track are added or
+// deleted, in the same transaction. Personal bests are kept under the main
+// account of drivers with linked Steam accounts, and hidden or banned drivers
+// don't get any.
+pub async fn refresh(
+    conn: &mut SqliteConnection,
+    track: &str,
+    steam_id: Option<i64>,
+) -> Result<()> {
+    let steam_id = match steam_id {
+        Some(steam_id) => Some(main_account(&mut *conn, steam_id).await?),
+        None => None,
+    };
+    sqlx::query!(
+        "DELETE FROM personal_best_laps WHERE track = ?1 AND (?2 IS NULL OR steam_id = ?2);",
+        track,
+        steam_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM personal_best_sectors WHERE track = ?1 AND (?2 IS NULL OR steam_id = ?2);",
+        track,
+        steam_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO personal_best_laps (
+            track, wet, steam_id, car_group, session_type, exclude_penalty_laps, lap_id, time_ms
+        )
+        SELECT track, wet, steam_id, car_group, session_type, exclude_penalty_laps, lap_id, time_ms
+        FROM (
+            SELECT s.track,
+                s.wet,
+                m.steam_id,
+                c.car_group,
+                s.type AS session_type,
+                e.exclude_penalty_laps,
+                l.id AS lap_id,
+                l.time_ms,
+                ROW_NUMBER() OVER (
+                    PARTITION BY s.wet, m.steam_id, c.car_group, s.type, e.exclude_penalty_laps
+                    ORDER BY l.time_ms, s.timestamp, l.id
+                ) AS rank
+            FROM laps l
+            INNER JOIN sessions s ON l.session_id = s.id
+            INNER JOIN cars c ON l.car_id = c.id
+            INNER JOIN drivers a ON l.steam_id = a.steam_id
+            INNER JOIN drivers m ON COALESCE(a.merged_into, a.steam_id) = m.steam_id
+            CROSS JOIN (SELECT 0 AS exclude_penalty_laps UNION ALL SELECT 1) e
+            WHERE s.track = ?1 AND (?2 IS NULL OR m.steam_id = ?2)
+            AND m.status = 'active'
+            AND l.valid = 1 AND l.excluded = 0
+            AND (e.exclude_penalty_laps = 0 OR l.penalty_served = 0)
+        )
+        WHERE rank = 1;",
+        track,
+        steam_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO personal_best_sectors (
+            track, wet, steam_id, car_group, session_type, exclude_penalty_laps, sector, time_ms
+        )
+        SELECT s.track,
+            s.wet,
+            m.steam_id,
+            c.car_group,
+            s.type,
+            e.exclude_penalty_laps,
+            sp.sector,
+            MIN(sp.time_ms)
+        FROM splits sp
+        INNER JOIN laps l ON sp.lap_id = l.id
+        INNER JOIN sessions s ON l.session_id = s.id
+        INNER JOIN cars c ON l.car_id = c.id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        INNER JOIN drivers m ON COALESCE(a.merged_into, a.steam_id) = m.steam_id
+        CROSS JOIN (SELECT 0 AS exclude_penalty_laps UNION ALL SELECT 1) e
+        WHERE s.track = ?1 AND (?2 IS NULL OR m.steam_id = ?2)
+        AND m.status = 'active'
+        AND l.valid = 1 AND l.excluded = 0
+        AND (e.exclude_penalty_laps = 0 OR l.penalty_served = 0)
+        GROUP BY s.wet, m.steam_id, c.car_group, s.type, e.exclude_penalty_laps, sp.sector;",
+        track,
+        steam_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+// Recalculates the personal bests of a driver on every track they drove on, for
+// when Steam accounts get linked or unlinked
+pub async fn refresh_driver(conn: &mut SqliteConnection, steam_id: i64) -> Result<()> {
+    let tracks = sqlx::query!(
+        "SELECT DISTINCT s.track
+        FROM sessions s
+        INNER JOIN laps l ON s.id = l.session_id
+        INNER JOIN drivers a ON l.steam_id = a.steam_id
+        WHERE COALESCE(a.merged_into, a.steam_id) = ?;",
+        steam_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in tracks {
+        refresh(&mut *conn, &row.track, Some(steam_id)).await?;
+    }
+    Ok(())
+}
+
+// Recalculates everything, for when the tables got out of sync somehow
+pub async fn rebuild(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query!("DELETE FROM personal_best_laps;")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!("DELETE FROM personal_best_sectors;")
+        .execute(&mut *conn)
+        .await?;
+    let tracks = sqlx::query!("SELECT DISTINCT track FROM sessions;")
+        .fetch_all(&mut *conn)
+        .await?;
+    for row in tracks {
+        refresh(&mut *conn, &row.track, None).await?;
+    }
+    Ok(())
+}